@@ -0,0 +1,58 @@
+#[macro_use]
+extern crate criterion;
+extern crate huff;
+
+use criterion::{black_box, Criterion, Throughput};
+use huff::{BitWriter, HuffBuilder, HuffWriter};
+
+const PARAGRAPH: &str = "It is a truth universally acknowledged, that a single man in \
+possession of a good fortune, must be in want of a wife. However little known the \
+feelings or views of such a man may be on his first entering a neighbourhood, this \
+truth is so well fixed in the minds of the surrounding families, that he is \
+considered the rightful property of some one or other of their daughters.\n";
+
+fn corpus() -> Vec<u8> {
+    PARAGRAPH.as_bytes().iter().cycle().take(1 << 20).cloned().collect()
+}
+
+fn writer(c: &mut Criterion) {
+    let data = corpus();
+    let tree = HuffBuilder::from_bytes(&data).build().unwrap();
+    let encoding = tree.clone().encoding();
+
+    let mut group = c.benchmark_group("writer");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+
+    group.bench_function("bit at a time", |b| {
+        b.iter(|| {
+            let mut writer = BitWriter::new(Vec::with_capacity(data.len()));
+            for byte in &data {
+                for bit in &encoding[byte] {
+                    writer.write_bit(*bit).unwrap();
+                }
+            }
+            black_box(writer.finish().unwrap())
+        })
+    });
+
+    group.bench_function("whole codes", |b| {
+        b.iter(|| {
+            let mut writer = HuffWriter::new(tree.clone(), Vec::with_capacity(data.len()));
+            writer.write_all(&data).unwrap();
+            black_box(writer.finish().unwrap())
+        })
+    });
+
+    group.bench_function("indexed codes", |b| {
+        b.iter(|| {
+            let mut writer = HuffWriter::new_indexed(tree.clone(), Vec::with_capacity(data.len()));
+            writer.write_all(&data).unwrap();
+            black_box(writer.finish().unwrap())
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, writer);
+criterion_main!(benches);
@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io::prelude::*;
+use std::io::Result;
+
+use bits::{BitReader, BitWriter};
+use HuffError;
+
+/// Symbols that can be sent as raw bits the first time they are seen by an
+/// adaptive coder.
+pub trait RawSymbol: Sized {
+    /// Number of bits in the raw form of a symbol.
+    const BITS: usize;
+
+    fn to_raw(&self) -> u32;
+
+    /// Turns raw bits back into a symbol, `None` if they are not a valid
+    /// symbol.
+    fn from_raw(raw: u32) -> Option<Self>;
+}
+
+impl RawSymbol for u8 {
+    const BITS: usize = 8;
+
+    fn to_raw(&self) -> u32 {
+        u32::from(*self)
+    }
+
+    fn from_raw(raw: u32) -> Option<Self> {
+        Some(raw as u8)
+    }
+}
+
+impl RawSymbol for u16 {
+    const BITS: usize = 16;
+
+    fn to_raw(&self) -> u32 {
+        u32::from(*self)
+    }
+
+    fn from_raw(raw: u32) -> Option<Self> {
+        Some(raw as u16)
+    }
+}
+
+impl RawSymbol for u32 {
+    const BITS: usize = 32;
+
+    fn to_raw(&self) -> u32 {
+        *self
+    }
+
+    fn from_raw(raw: u32) -> Option<Self> {
+        Some(raw)
+    }
+}
+
+impl RawSymbol for char {
+    const BITS: usize = 21;
+
+    fn to_raw(&self) -> u32 {
+        *self as u32
+    }
+
+    fn from_raw(raw: u32) -> Option<Self> {
+        ::std::char::from_u32(raw)
+    }
+}
+
+struct Node<V> {
+    weight: u64,
+    parent: Option<usize>,
+    children: Option<(usize, usize)>,
+    symbol: Option<V>,
+}
+
+// An FGK tree. Nodes are kept in `order` from the highest number, the root,
+// down to the lowest, the NYT node, so that weights never increase along
+// `order` and siblings sit next to each other.
+struct AdaptiveTree<V> {
+    nodes: Vec<Node<V>>,
+    order: Vec<usize>,
+    position: Vec<usize>,
+    leaves: HashMap<V, usize>,
+    nyt: usize,
+}
+
+impl<V: Eq + Hash + Clone> AdaptiveTree<V> {
+    fn new() -> Self {
+        AdaptiveTree {
+            nodes: vec![
+                Node {
+                    weight: 0,
+                    parent: None,
+                    children: None,
+                    symbol: None,
+                },
+            ],
+            order: vec![0],
+            position: vec![0],
+            leaves: HashMap::new(),
+            nyt: 0,
+        }
+    }
+
+    fn root(&self) -> usize {
+        self.order[0]
+    }
+
+    // the code for a node, read from the node up to the root
+    fn code(&self, mut node: usize) -> Vec<bool> {
+        let mut code = vec![];
+        while let Some(parent) = self.nodes[node].parent {
+            let (_, right) = self.nodes[parent].children.unwrap();
+            code.push(node == right);
+            node = parent;
+        }
+
+        code.reverse();
+        code
+    }
+
+    fn push_node(&mut self, parent: usize, symbol: Option<V>) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            weight: 0,
+            parent: Some(parent),
+            children: None,
+            symbol,
+        });
+        self.position.push(self.order.len());
+        self.order.push(index);
+        index
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        let a_parent = self.nodes[a].parent.unwrap();
+        let b_parent = self.nodes[b].parent.unwrap();
+
+        self.replace_child(a_parent, a, b);
+        self.replace_child(b_parent, b, a);
+        self.nodes[a].parent = Some(b_parent);
+        self.nodes[b].parent = Some(a_parent);
+
+        let (a_pos, b_pos) = (self.position[a], self.position[b]);
+        self.order.swap(a_pos, b_pos);
+        self.position[a] = b_pos;
+        self.position[b] = a_pos;
+    }
+
+    fn replace_child(&mut self, parent: usize, old: usize, new: usize) {
+        let children = self.nodes[parent].children.as_mut().unwrap();
+        if children.0 == old {
+            children.0 = new;
+        } else {
+            children.1 = new;
+        }
+    }
+
+    // the highest numbered node with the same weight as `node`, only
+    // considering leaves if `leaves_only` is set
+    fn leader(&self, node: usize, leaves_only: bool) -> usize {
+        let weight = self.nodes[node].weight;
+        let mut leader = node;
+
+        for &other in self.order[..self.position[node]].iter().rev() {
+            if self.nodes[other].weight != weight {
+                break;
+            }
+            if !leaves_only || self.nodes[other].children.is_none() {
+                leader = other;
+            }
+        }
+
+        leader
+    }
+
+    fn is_nyt_sibling(&self, node: usize) -> bool {
+        let nyt_parent = self.nodes[self.nyt].parent;
+        nyt_parent.is_some() && self.nodes[node].parent == nyt_parent && node != self.nyt
+    }
+
+    fn update(&mut self, symbol: &V) {
+        let mut node = match self.leaves.get(symbol) {
+            Some(&leaf) => leaf,
+            None => {
+                // the NYT node splits into a new NYT node on the left and the
+                // new symbol on the right
+                let parent = self.nyt;
+                let leaf = self.push_node(parent, Some(symbol.clone()));
+                let nyt = self.push_node(parent, None);
+                self.nodes[parent].children = Some((nyt, leaf));
+                self.nyt = nyt;
+                self.leaves.insert(symbol.clone(), leaf);
+                leaf
+            }
+        };
+
+        if self.is_nyt_sibling(node) {
+            let leader = self.leader(node, true);
+            if leader != node {
+                self.swap(node, leader);
+            }
+            self.nodes[node].weight += 1;
+            node = self.nodes[node].parent.unwrap();
+        }
+
+        while node != self.root() {
+            let leader = self.leader(node, false);
+            if leader != node {
+                self.swap(node, leader);
+            }
+            self.nodes[node].weight += 1;
+            node = self.nodes[node].parent.unwrap();
+        }
+
+        self.nodes[node].weight += 1;
+    }
+}
+
+/// Encodes symbols with the FGK adaptive Huffman algorithm.
+///
+/// No tree needs to be agreed on up front. Each symbol's code comes from the
+/// counts of the symbols written before it, and a symbol seen for the first
+/// time is written as the code of the not-yet-transmitted node followed by
+/// its raw bits.
+pub struct AdaptiveHuffWriter<V: Eq + Hash + Clone + RawSymbol, W: Write> {
+    tree: AdaptiveTree<V>,
+    writer: BitWriter<W>,
+}
+
+impl<V: Eq + Hash + Clone + RawSymbol, W: Write> AdaptiveHuffWriter<V, W> {
+    pub fn new(writer: W) -> Self {
+        AdaptiveHuffWriter {
+            tree: AdaptiveTree::new(),
+            writer: BitWriter::new(writer),
+        }
+    }
+
+    pub fn write(&mut self, value: &V) -> Result<()> {
+        match self.tree.leaves.get(value) {
+            Some(&leaf) => {
+                for bit in self.tree.code(leaf) {
+                    self.writer.write_bit(bit)?;
+                }
+            }
+            None => {
+                for bit in self.tree.code(self.tree.nyt) {
+                    self.writer.write_bit(bit)?;
+                }
+
+                let raw = value.to_raw();
+                for i in (0..V::BITS).rev() {
+                    self.writer.write_bit(raw >> i & 1 == 1)?;
+                }
+            }
+        }
+
+        self.tree.update(value);
+
+        Ok(())
+    }
+
+    /// Writes out any buffered bits and hands back the inner writer.
+    ///
+    /// The final byte is padded with `0` bits.
+    pub fn finish(self) -> Result<W> {
+        self.writer.finish()
+    }
+}
+
+/// Decodes symbols written by `AdaptiveHuffWriter`.
+pub struct AdaptiveHuffReader<V: Eq + Hash + Clone + RawSymbol, R: Read> {
+    tree: AdaptiveTree<V>,
+    reader: BitReader<R>,
+}
+
+impl<V: Eq + Hash + Clone + RawSymbol, R: Read> AdaptiveHuffReader<V, R> {
+    pub fn new(reader: R) -> Self {
+        AdaptiveHuffReader {
+            tree: AdaptiveTree::new(),
+            reader: BitReader::new(reader),
+        }
+    }
+
+    pub fn read(&mut self) -> Result<V> {
+        let mut node = self.tree.root();
+
+        while let Some((left, right)) = self.tree.nodes[node].children {
+            node = if self.read_bit()? { right } else { left };
+        }
+
+        let value = match self.tree.nodes[node].symbol {
+            Some(ref value) => value.clone(),
+            None => {
+                let mut raw = 0;
+                for _ in 0..V::BITS {
+                    raw = raw << 1 | self.read_bit()? as u32;
+                }
+
+                match V::from_raw(raw) {
+                    Some(value) => value,
+                    None => return Err(HuffError::InvalidCode.into()),
+                }
+            }
+        };
+
+        self.tree.update(&value);
+
+        Ok(value)
+    }
+
+    fn read_bit(&mut self) -> Result<bool> {
+        match self.reader.read_bit()? {
+            Some(bit) => Ok(bit),
+            None => Err(HuffError::UnexpectedEof.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn adaptive_roundtrip() {
+        let mut input = vec![];
+        for i in 0..1000u32 {
+            input.push(match i % 10 {
+                0 => b'c',
+                1 | 2 => b'b',
+                _ => b'a',
+            });
+        }
+        input.extend_from_slice(b"the quick brown fox");
+
+        let mut writer = AdaptiveHuffWriter::new(vec![]);
+        for value in &input {
+            writer.write(value).unwrap();
+        }
+        let output = writer.finish().unwrap();
+
+        // well under the 8 bits per symbol of a fixed code
+        assert!(output.len() < input.len() / 4);
+
+        let mut reader = AdaptiveHuffReader::<u8, _>::new(Cursor::new(output));
+        let mut decoded = vec![];
+        for _ in 0..input.len() {
+            decoded.push(reader.read().unwrap());
+        }
+
+        assert_eq!(input, decoded);
+    }
+
+    #[test]
+    fn adaptive_chars() {
+        let input: Vec<char> = "mississippi river".chars().collect();
+
+        let mut writer = AdaptiveHuffWriter::new(vec![]);
+        for value in &input {
+            writer.write(value).unwrap();
+        }
+        let output = writer.finish().unwrap();
+
+        let mut reader = AdaptiveHuffReader::<char, _>::new(Cursor::new(output));
+        let mut decoded = vec![];
+        for _ in 0..input.len() {
+            decoded.push(reader.read().unwrap());
+        }
+
+        assert_eq!(input, decoded);
+    }
+}
@@ -0,0 +1,284 @@
+use std::io::prelude::*;
+use std::io::{ErrorKind, Result};
+
+/// The order bits are packed into each byte.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BitOrder {
+    /// The first bit goes in the most significant bit of the byte.
+    MsbFirst,
+    /// The first bit goes in the least significant bit of the byte.
+    LsbFirst,
+}
+
+impl BitOrder {
+    // mask for the bit at `index` within a byte, counting in stream order
+    fn mask(self, index: u8) -> u8 {
+        match self {
+            BitOrder::MsbFirst => 0b_1000_0000 >> index,
+            BitOrder::LsbFirst => 0b_0000_0001 << index,
+        }
+    }
+
+    // turns a byte with its first bit in the most significant place into
+    // this order
+    fn byte(self, byte: u8) -> u8 {
+        match self {
+            BitOrder::MsbFirst => byte,
+            BitOrder::LsbFirst => byte.reverse_bits(),
+        }
+    }
+}
+
+/// Writes bits to a byte writer.
+///
+/// Bits are gathered in a 64 bit accumulator and written out as whole bytes
+/// once it fills, so the inner writer sees a few large writes rather than
+/// one per byte. Any partial byte is padded with `0` bits by `finish`, or
+/// when the writer is dropped.
+///
+/// Only `finish` reports errors writing out the last bits. Dropping the
+/// writer instead ignores them in release builds and panics in debug
+/// builds.
+pub struct BitWriter<W: Write> {
+    inner: Option<W>,
+    order: BitOrder,
+    // the last `fill` bits in stream order, the first in the highest place
+    acc: u64,
+    fill: u32,
+    total: u64,
+}
+
+impl<W: Write> BitWriter<W> {
+    /// The most bits `write_bits` takes at once.
+    pub const MAX_BITS: u32 = 56;
+
+    pub fn new(inner: W) -> Self {
+        BitWriter::with_order(inner, BitOrder::MsbFirst)
+    }
+
+    /// Creates a writer that packs bits into each byte in the given order.
+    pub fn with_order(inner: W, order: BitOrder) -> Self {
+        BitWriter {
+            inner: Some(inner),
+            order,
+            acc: 0,
+            fill: 0,
+            total: 0,
+        }
+    }
+
+    pub fn write_bit(&mut self, bit: bool) -> Result<()> {
+        self.write_bits(bit as u64, 1)
+    }
+
+    /// Writes the low `len` bits of `bits`, most significant first.
+    ///
+    /// Panics if `len` is more than `MAX_BITS`.
+    pub fn write_bits(&mut self, bits: u64, len: u32) -> Result<()> {
+        assert!(len <= Self::MAX_BITS, "at most {} bits can be written at once", Self::MAX_BITS);
+
+        if self.fill + len > 64 {
+            self.write_bytes()?;
+        }
+
+        if len > 0 {
+            self.acc = self.acc << len | bits & (u64::MAX >> (64 - len));
+            self.fill += len;
+            self.total += u64::from(len);
+        }
+
+        Ok(())
+    }
+
+    /// Number of bits written so far, counting any from `align_to_byte` but
+    /// not the padding `finish` adds.
+    pub fn bits_written(&self) -> u64 {
+        self.total
+    }
+
+    /// How many bits into the current byte the next bit goes, from 0 on a
+    /// byte boundary to 7.
+    pub fn bit_position(&self) -> usize {
+        (self.fill % 8) as usize
+    }
+
+    /// The order bits are packed into each byte.
+    pub fn order(&self) -> BitOrder {
+        self.order
+    }
+
+    /// Pads the current byte with `0` bits so the next bit starts a new
+    /// byte, returning how many bits were added.
+    pub fn align_to_byte(&mut self) -> Result<usize> {
+        let padding = (8 - self.fill % 8) % 8;
+        self.write_bits(0, padding)?;
+
+        Ok(padding as usize)
+    }
+
+    /// Writes out any partial byte and hands back the inner writer.
+    pub fn finish(mut self) -> Result<W> {
+        let flushed = self.flush_bits();
+        // the error is reported here, so there is nothing left for drop
+        self.fill = 0;
+        flushed?;
+
+        let mut inner = self.inner.take().unwrap();
+        inner.flush()?;
+
+        Ok(inner)
+    }
+
+    // writes out every whole byte in the accumulator
+    fn write_bytes(&mut self) -> Result<()> {
+        let mut bytes = [0; 8];
+        let mut count = 0;
+        while self.fill >= 8 {
+            self.fill -= 8;
+            bytes[count] = self.order.byte((self.acc >> self.fill) as u8);
+            count += 1;
+        }
+
+        if let Some(ref mut inner) = self.inner {
+            inner.write_all(&bytes[..count])?;
+        }
+
+        Ok(())
+    }
+
+    // writes out everything in the accumulator, with the unfilled bits of
+    // the last byte left as zero
+    fn flush_bits(&mut self) -> Result<()> {
+        self.write_bytes()?;
+
+        if self.fill > 0 {
+            let byte = self.order.byte((self.acc << (8 - self.fill)) as u8);
+            if let Some(ref mut inner) = self.inner {
+                inner.write_all(&[byte])?;
+            }
+            self.fill = 0;
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for BitWriter<W> {
+    fn drop(&mut self) {
+        // `finish` is the way to see this error, but bits lost silently are
+        // worth catching in debug builds
+        let buffered = self.fill;
+        if let Err(e) = self.flush_bits() {
+            debug_assert!(
+                ::std::thread::panicking(),
+                "BitWriter dropped without `finish` lost up to {} buffered bits: {}",
+                buffered,
+                e
+            );
+        }
+    }
+}
+
+pub struct BitReader<R: Read> {
+    inner: R,
+    order: BitOrder,
+    byte: u8,
+    read: u8,
+    total: u64,
+    unread: Vec<bool>,
+}
+
+impl<R: Read> BitReader<R> {
+    pub fn new(inner: R) -> Self {
+        BitReader::with_order(inner, BitOrder::MsbFirst)
+    }
+
+    pub fn with_order(inner: R, order: BitOrder) -> Self {
+        BitReader {
+            inner,
+            order,
+            byte: 0,
+            read: 8,
+            total: 0,
+            unread: vec![],
+        }
+    }
+
+    /// Reads the next bit, or `None` at the end of the input.
+    ///
+    /// Bytes are only pulled from the inner reader once all bits of the
+    /// previous byte have been read.
+    pub fn read_bit(&mut self) -> Result<Option<bool>> {
+        if let Some(bit) = self.unread.pop() {
+            self.total += 1;
+            return Ok(Some(bit));
+        }
+
+        if self.read == 8 {
+            match self.next_byte()? {
+                Some(byte) => self.byte = byte,
+                None => return Ok(None),
+            }
+            self.read = 0;
+        }
+
+        let bit = self.byte & self.order.mask(self.read) != 0;
+        self.read += 1;
+        self.total += 1;
+
+        Ok(Some(bit))
+    }
+
+    /// Puts back bits that were read, so they are read again in the same
+    /// order.
+    pub fn unread(&mut self, bits: &[bool]) {
+        self.unread.extend(bits.iter().rev());
+        self.total -= bits.len() as u64;
+    }
+
+    /// Skips the rest of the current byte, returning how many bits were
+    /// skipped.
+    ///
+    /// Bytes are counted from where this reader started in the inner reader.
+    // `u64::is_multiple_of` is newer than the compilers this crate supports
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn skip_to_byte(&mut self) -> Result<usize> {
+        let mut skipped = 0;
+        while self.total % 8 != 0 && self.read_bit()?.is_some() {
+            skipped += 1;
+        }
+
+        Ok(skipped)
+    }
+
+    /// Puts back the low `len` bits of `bits`, so they are read again most
+    /// significant first.
+    pub fn unread_bits(&mut self, bits: u64, len: usize) {
+        self.unread.extend((0..len).map(|i| bits >> i & 1 == 1));
+        self.total -= len as u64;
+    }
+
+    pub fn bits_read(&self) -> u64 {
+        self.total
+    }
+
+    pub fn order(&self) -> BitOrder {
+        self.order
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn next_byte(&mut self) -> Result<Option<u8>> {
+        let mut buf = [0];
+        loop {
+            match self.inner.read(&mut buf) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(buf[0])),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
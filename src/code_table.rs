@@ -0,0 +1,84 @@
+use alloc::vec::Vec;
+
+use map::{Map, MapKey};
+
+#[cfg(feature = "serde")]
+use serde::de::{self, Deserialize, Deserializer};
+#[cfg(feature = "serde")]
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+/// A symbol to code map, as produced by `HuffTree::encoding`.
+///
+/// With the `serde` feature each code is serialized as its length in bits
+/// along with the bits packed most significant bit first into bytes, rather
+/// than as a list of booleans.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CodeTable<V: MapKey>(pub Map<V, Vec<bool>>);
+
+impl<V: MapKey> From<Map<V, Vec<bool>>> for CodeTable<V> {
+    fn from(map: Map<V, Vec<bool>>) -> Self {
+        CodeTable(map)
+    }
+}
+
+#[cfg(feature = "serde")]
+fn pack(code: &[bool]) -> Vec<u8> {
+    code.chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0, |byte, (i, bit)| byte | (*bit as u8) << (7 - i))
+        })
+        .collect()
+}
+
+#[cfg(feature = "serde")]
+impl<V: MapKey + Serialize> Serialize for CodeTable<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (value, code) in &self.0 {
+            map.serialize_entry(value, &(code.len(), pack(code)))?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, V: MapKey + Deserialize<'de>> Deserialize<'de> for CodeTable<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let packed: Map<V, (usize, Vec<u8>)> = Map::deserialize(deserializer)?;
+
+        let mut map = Map::new();
+        for (value, (len, bytes)) in packed {
+            if bytes.len() * 8 < len {
+                let expected = "enough bytes for the code length";
+                return Err(de::Error::invalid_length(bytes.len(), &expected));
+            }
+
+            let code = (0..len).map(|i| bytes[i / 8] & 0b_1000_0000 >> (i % 8) != 0).collect();
+            map.insert(value, code);
+        }
+
+        Ok(CodeTable(map))
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    extern crate serde_json;
+
+    use super::*;
+
+    #[test]
+    fn code_table_json_roundtrip() {
+        let mut map = Map::new();
+        map.insert('a', vec![true, false, true, true, false, false, true, false, true]);
+        let table = CodeTable(map);
+
+        let json = serde_json::to_string(&table).unwrap();
+
+        assert_eq!(r#"{"a":[9,[178,128]]}"#, json);
+        assert_eq!(table, serde_json::from_str(&json).unwrap());
+    }
+}
@@ -0,0 +1,115 @@
+use alloc::vec::Vec;
+
+use map::{Map, MapKey};
+use HuffTree;
+
+/// Codes symbols to and from bits held in memory as `bool`s, without going
+/// through `std::io`.
+///
+/// Meant for formats that manage their own bit buffers, and for checking
+/// coding logic without a `Cursor`. Works without the `std` feature.
+#[derive(Debug, Clone)]
+pub struct HuffCodec<V: Eq + Clone + MapKey> {
+    tree: HuffTree<V>,
+    codes: Map<V, Vec<bool>>,
+}
+
+impl<V: Eq + Clone + MapKey> HuffCodec<V> {
+    pub fn new(tree: HuffTree<V>) -> Self {
+        let codes = tree.encoding_ref();
+        HuffCodec { tree, codes }
+    }
+
+    pub fn tree(&self) -> &HuffTree<V> {
+        &self.tree
+    }
+
+    /// The codes of every symbol in `data` one after another, or `None` if
+    /// a symbol is not in the tree.
+    pub fn encode(&self, data: &[V]) -> Option<Vec<bool>> {
+        let mut bits = vec![];
+        for value in data {
+            bits.extend_from_slice(self.codes.get(value)?);
+        }
+
+        Some(bits)
+    }
+
+    /// Decodes `count` symbols from the start of `bits`.
+    ///
+    /// Returns `None` if `bits` ends before `count` symbols, or holds bits
+    /// that are not a code of the tree. Bits after the last symbol are
+    /// ignored.
+    pub fn decode(&self, bits: &[bool], count: usize) -> Option<Vec<V>> {
+        let mut bits = bits.iter();
+        let mut values = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            values.push(self.decode_next(&mut bits)?);
+        }
+
+        Some(values)
+    }
+
+    fn decode_next<'a, I: Iterator<Item = &'a bool>>(&self, bits: &mut I) -> Option<V> {
+        // a lone leaf is encoded as a single `0` bit, see `encoding`
+        if let HuffTree::Leaf(ref value) = self.tree {
+            return match *bits.next()? {
+                false => Some(value.clone()),
+                true => None,
+            };
+        }
+
+        let mut cursor = &self.tree;
+        loop {
+            match *cursor {
+                HuffTree::Leaf(ref value) => return Some(value.clone()),
+                HuffTree::Node(ref l, ref r) => cursor = if *bits.next()? { r } else { l },
+            }
+        }
+    }
+}
+
+// the tree may be deep, see `HuffTree::drop_deep`
+impl<V: Eq + Clone + MapKey> Drop for HuffCodec<V> {
+    fn drop(&mut self) {
+        self.tree.balance_for_drop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use HuffBuilder;
+
+    #[test]
+    fn codec_roundtrip() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+        let codec = HuffCodec::new(tree);
+
+        let bits = codec.encode(&['a', 'b', 'd']).unwrap();
+        assert_eq!(vec![true, true, true, false, false], bits);
+        assert_eq!(Some(vec!['a', 'b', 'd']), codec.decode(&bits, 3));
+        assert_eq!(Some(vec!['a']), codec.decode(&bits, 1));
+
+        // `d` is one bit, so the last two bits are not enough for a fourth
+        assert_eq!(None, codec.decode(&bits[..4], 3));
+        assert_eq!(None, codec.decode(&bits, 4));
+        assert_eq!(None, codec.encode(&['a', 'z']));
+    }
+
+    #[test]
+    fn codec_lone_leaf() {
+        let codec = HuffCodec::new(HuffTree::new_leaf('a'));
+
+        let bits = codec.encode(&['a', 'a']).unwrap();
+        assert_eq!(vec![false, false], bits);
+        assert_eq!(Some(vec!['a', 'a']), codec.decode(&bits, 2));
+        assert_eq!(None, codec.decode(&[true], 1));
+    }
+}
@@ -0,0 +1,235 @@
+use std::fmt::Debug;
+use std::io::prelude::*;
+use std::io::{Error, ErrorKind, Result};
+
+use bits::BitReader;
+use map::MapKey;
+use {BuildError, HuffBuilder, HuffError, HuffTree, HuffWriter, RawSymbol};
+
+/// Compresses `data` into a self-describing buffer that `decompress` reads
+/// back without anything else to go on.
+///
+/// The buffer holds the number of symbols as a big endian `u64`, then the
+/// tree built from the symbol counts in `data` as written by
+/// `HuffTree::serialize_with`, with each leaf in the fewest whole bytes that
+/// hold its `RawSymbol` bits, most significant first, and last the coded
+/// symbols padded to a byte. Empty data is just the count, with no tree.
+pub fn compress<V>(data: &[V]) -> Result<Vec<u8>>
+where
+    V: RawSymbol + Eq + Clone + MapKey + Ord + Debug,
+{
+    let mut out = (data.len() as u64).to_be_bytes().to_vec();
+
+    let mut builder = HuffBuilder::new();
+    for value in data {
+        builder.observe(value.clone());
+    }
+
+    let tree = match builder.build() {
+        Some(tree) => tree,
+        None => return Ok(out),
+    };
+
+    tree.serialize_with(&mut out, |w, v| w.write_all(&raw_bytes::<V>(&v)))?;
+
+    let mut writer = HuffWriter::new(tree, out);
+    writer.write_all(data)?;
+    writer.finish()
+}
+
+/// Reads back data written by `compress`.
+///
+/// Input that ends before the recorded number of symbols fails with
+/// `UnexpectedEof`, and a leaf that is not a valid symbol with
+/// `InvalidData`.
+pub fn decompress<V>(bytes: &[u8]) -> Result<Vec<V>>
+where
+    V: RawSymbol + Eq + Clone + Debug,
+{
+    let mut reader = bytes;
+
+    let mut count = [0; 8];
+    reader.read_exact(&mut count)?;
+    let count = u64::from_be_bytes(count);
+    if count == 0 {
+        return Ok(vec![]);
+    }
+
+    let tree = HuffTree::deserialize_with(&mut reader, |r| {
+        let mut raw = [0; 4];
+        let raw = &mut raw[4 - raw_len::<V>()..];
+        r.read_exact(raw)?;
+
+        let raw = raw.iter().fold(0, |raw, byte| raw << 8 | u32::from(*byte));
+        V::from_raw(raw).ok_or_else(|| {
+            Error::new(ErrorKind::InvalidData, format!("{:#x} is not a valid symbol", raw))
+        })
+    })?;
+
+    let data = decode_body(&tree, reader, count);
+    // the tree came from the input, so it may be deep
+    tree.drop_deep();
+
+    data
+}
+
+fn decode_body<V: Eq + Clone>(tree: &HuffTree<V>, reader: &[u8], count: u64) -> Result<Vec<V>> {
+    // every symbol takes at least a bit, which also bounds what is allocated
+    if count > reader.len() as u64 * 8 {
+        return Err(HuffError::UnexpectedEof.into());
+    }
+
+    let data = tree.decode_all(reader, count as usize)?;
+    if data.len() as u64 != count {
+        return Err(HuffError::UnexpectedEof.into());
+    }
+
+    Ok(data)
+}
+
+/// Codes `s` with a tree built from its characters, returning the packed
+/// bytes, the exact number of bits in them and the tree.
+///
+/// Fails with `BuildError::Empty` for an empty string. The bit count is what
+/// lets `decompress_str` stop before the padding of the last byte, which
+/// would otherwise decode to extra characters.
+pub fn compress_str(s: &str) -> std::result::Result<(Vec<u8>, usize, HuffTree<char>), BuildError> {
+    let mut builder = HuffBuilder::new();
+    for c in s.chars() {
+        builder.observe(c);
+    }
+
+    let tree = builder.try_build()?;
+    let data: Vec<char> = s.chars().collect();
+    let (bytes, bit_len) = tree.encode_slice(&data).expect("every character is in the tree");
+
+    Ok((bytes, bit_len, tree))
+}
+
+/// Decodes the first `bit_len` bits of `bytes` with `tree`, as returned by
+/// `compress_str`.
+///
+/// Fails with `UnexpectedEof` if the bits end part way through a character
+/// or `bytes` holds fewer than `bit_len` bits.
+pub fn decompress_str(bytes: &[u8], bit_len: usize, tree: &HuffTree<char>) -> Result<String> {
+    let mut reader = BitReader::new(bytes);
+    let mut left = bit_len;
+    let mut next_bit = || {
+        if left == 0 {
+            return Ok(None);
+        }
+        left -= 1;
+
+        match reader.read_bit()? {
+            Some(bit) => Ok(Some(bit)),
+            None => Err(HuffError::UnexpectedEof.into()),
+        }
+    };
+
+    let mut string = String::new();
+    while let Some(c) = tree.decode_with(&mut next_bit)? {
+        string.push(c);
+    }
+
+    Ok(string)
+}
+
+fn raw_len<V: RawSymbol>() -> usize {
+    V::BITS.div_ceil(8)
+}
+
+fn raw_bytes<V: RawSymbol>(value: &V) -> Vec<u8> {
+    value.to_raw().to_be_bytes()[4 - raw_len::<V>()..].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use HuffReader;
+
+    #[test]
+    fn compress_roundtrip() {
+        let data: Vec<char> = "hello world, hello huffman".chars().collect();
+
+        let bytes = compress(&data).unwrap();
+        assert_eq!((data.len() as u64).to_be_bytes(), bytes[..8]);
+        assert_eq!(data, decompress::<char>(&bytes).unwrap());
+
+        let data = vec![7u16; 20];
+        assert_eq!(data, decompress::<u16>(&compress(&data).unwrap()).unwrap());
+
+        let bytes = compress::<u8>(&[]).unwrap();
+        assert_eq!(vec![0; 8], bytes);
+        assert!(decompress::<u8>(&bytes).unwrap().is_empty());
+    }
+
+    #[test]
+    fn compress_str_roundtrip() {
+        let s = "ööh, ääh, ööh — 日本日本日本";
+
+        let (bytes, bit_len, tree) = compress_str(s).unwrap();
+        assert!(bit_len <= bytes.len() * 8 && bit_len > bytes.len() * 8 - 8);
+        assert_eq!(s, decompress_str(&bytes, bit_len, &tree).unwrap());
+
+        // the padding is extra characters, or part of one, if not cut off
+        assert_ne!(0, bit_len % 8);
+        let padded = decompress_str(&bytes, bytes.len() * 8, &tree);
+        assert_ne!(Some(s), padded.as_ref().ok().map(String::as_str));
+
+        let err = decompress_str(&bytes, bit_len + 8, &tree).unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+
+        assert_eq!(Err(BuildError::Empty), compress_str("").map(|_| ()));
+    }
+
+    #[test]
+    fn decompress_truncated() {
+        let data = b"abracadabra".to_vec();
+        let bytes = compress(&data).unwrap();
+
+        for len in 0..bytes.len() {
+            let err = decompress::<u8>(&bytes[..len]).unwrap_err();
+            assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+        }
+    }
+
+    const SPINE: usize = 150_000;
+
+    // a tree leaning all the way left, as `SPINE` node bits and then one more
+    // leaf bit, with the root's right leaf coded `1` holding `SPINE as u8`
+    fn deep_spine() -> Vec<u8> {
+        let mut bytes = vec![0xff; SPINE / 8];
+        bytes.extend(vec![0; (SPINE + 1).div_ceil(8)]);
+        bytes.extend((0..=SPINE).map(|i| i as u8));
+        bytes
+    }
+
+    #[test]
+    fn decompress_deep_spine() {
+        let mut bytes = 1u64.to_be_bytes().to_vec();
+        bytes.extend(deep_spine());
+        bytes.push(0b_10000000);
+
+        assert_eq!(vec![SPINE as u8], decompress::<u8>(&bytes).unwrap());
+    }
+
+    #[test]
+    fn reader_over_deep_spine() {
+        let tree = HuffTree::<u8>::deserialize(&mut &deep_spine()[..]).unwrap();
+        assert_eq!(SPINE, tree.max_code_length());
+
+        let mut reader = HuffReader::new(tree, Cursor::new(vec![0b_10000000]));
+        assert_eq!(SPINE as u8, reader.read().unwrap());
+    }
+
+    #[test]
+    fn decompress_invalid_symbol() {
+        // one `char` leaf holding a surrogate
+        let mut bytes = 1u64.to_be_bytes().to_vec();
+        bytes.extend(&[0b_00000000, 0x00, 0xd8, 0x00, 0b_00000000]);
+
+        let err = decompress::<char>(&bytes).unwrap_err();
+        assert_eq!(ErrorKind::InvalidData, err.kind());
+    }
+}
@@ -0,0 +1,130 @@
+use alloc::vec::Vec;
+
+use HuffTree;
+
+/// A lookup table for decoding, indexed by the next `bits()` bits of input
+/// with the first bit as the most significant.
+///
+/// Each entry holds the symbol whose code starts those bits and the length
+/// of that code, so a symbol is decoded with a single lookup rather than a
+/// walk down the tree.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DecodeTable<V> {
+    bits: usize,
+    entries: Vec<Option<(V, usize)>>,
+}
+
+impl<V> DecodeTable<V> {
+    /// The longest code length allowed in a table, limiting tables to 2^16
+    /// entries.
+    pub const MAX_BITS: usize = 16;
+
+    /// Number of bits used to index the table.
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// The symbol and code length for the given `bits()` bits of input, or
+    /// `None` if they do not start with any code.
+    pub fn lookup(&self, index: usize) -> Option<(&V, usize)> {
+        match self.entries.get(index) {
+            Some(&Some((ref v, len))) => Some((v, len)),
+            _ => None,
+        }
+    }
+}
+
+impl<V: Eq + Clone> HuffTree<V> {
+    /// Builds a table for decoding a symbol with one lookup, along with the
+    /// number of entries that start no code.
+    ///
+    /// Every node has two children, so the codes of a tree fill the code
+    /// space and only a lone leaf, whose code `0` leaves `1` unused, has an
+    /// unused entry. A count above zero otherwise means the table does not
+    /// cover every input.
+    ///
+    /// Returns `None` if the longest code is over `DecodeTable::MAX_BITS`.
+    pub fn build_decode_table(&self) -> Option<(DecodeTable<V>, usize)> {
+        let bits = self.max_code_length();
+        if bits > DecodeTable::<V>::MAX_BITS {
+            return None;
+        }
+
+        let mut table = DecodeTable {
+            bits,
+            entries: vec![None; 1 << bits],
+        };
+
+        match *self {
+            // a lone leaf has the code `0`, see `encoding`
+            HuffTree::Leaf(ref v) => table.entries[0] = Some((v.clone(), 1)),
+            _ => self.fill_table(&mut table, 0, 0),
+        }
+
+        let unused = table.entries.iter().filter(|entry| entry.is_none()).count();
+        Some((table, unused))
+    }
+
+    fn fill_table(&self, table: &mut DecodeTable<V>, code: usize, len: usize) {
+        let mut stack = vec![(self, code, len)];
+        while let Some((tree, code, len)) = stack.pop() {
+            match *tree {
+                HuffTree::Leaf(ref v) => {
+                    let shift = table.bits - len;
+                    for index in code << shift..(code + 1) << shift {
+                        table.entries[index] = Some((v.clone(), len));
+                    }
+                }
+                HuffTree::Node(ref l, ref r) => {
+                    stack.push((r, code << 1 | 1, len + 1));
+                    stack.push((l, code << 1, len + 1));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use HuffBuilder;
+
+    #[test]
+    fn decode_table_lookup() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let (table, unused) = tree.build_decode_table().unwrap();
+
+        assert_eq!(0, unused);
+        assert_eq!(2, table.bits());
+        assert_eq!(Some((&'d', 1)), table.lookup(0b_00));
+        assert_eq!(Some((&'d', 1)), table.lookup(0b_01));
+        assert_eq!(Some((&'b', 2)), table.lookup(0b_10));
+        assert_eq!(Some((&'a', 2)), table.lookup(0b_11));
+
+        let (table, unused) = HuffTree::new_leaf('a').build_decode_table().unwrap();
+
+        assert_eq!(1, unused);
+        assert_eq!(Some((&'a', 1)), table.lookup(0));
+        assert_eq!(None, table.lookup(1));
+    }
+
+    #[test]
+    fn decode_table_too_deep() {
+        let mut builder = HuffBuilder::<usize, u64>::new();
+        let (mut a, mut b) = (1, 1);
+        for i in 0..20 {
+            builder = builder.add(i, a);
+            let next = a + b;
+            a = b;
+            b = next;
+        }
+
+        assert_eq!(None, builder.build().unwrap().build_decode_table());
+    }
+}
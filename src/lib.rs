@@ -1,204 +1,4802 @@
-extern crate bitstream;
+//! Huffman coding over any symbol type.
+//!
+//! The `std` feature, on by default, adds the readers and writers that work
+//! with `std::io`. Without it the crate is `no_std` and needs only `alloc`,
+//! keeping trees, builders and code tables, with code tables held in a
+//! `BTreeMap` rather than a `HashMap`.
 
-use bitstream::{BitWriter, BitReader, NoPadding};
+#![cfg_attr(not(feature = "std"), no_std)]
 
+#[macro_use]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate core;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+
+#[cfg(feature = "std")]
+mod adaptive;
+#[cfg(feature = "std")]
+mod bits;
+mod codec;
+mod code_table;
+#[cfg(feature = "std")]
+mod compress;
+mod decode_table;
+mod map;
+#[cfg(feature = "std")]
+mod multi;
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "std")]
+pub use adaptive::{AdaptiveHuffReader, AdaptiveHuffWriter, RawSymbol};
+#[cfg(feature = "std")]
+pub use bits::{BitOrder, BitWriter};
+pub use code_table::CodeTable;
+pub use codec::HuffCodec;
+#[cfg(feature = "std")]
+pub use compress::{compress, compress_str, decompress, decompress_str};
+pub use decode_table::DecodeTable;
+pub use map::{Map, MapKey};
+#[cfg(feature = "std")]
+pub use multi::{MultiHuffReader, MultiHuffWriter, StreamReader};
+#[cfg(feature = "std")]
+use bits::BitReader;
+use map::Set;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt::{self, Debug};
+use core::iter::FromIterator;
+use core::mem;
+#[cfg(feature = "std")]
 use std::io::prelude::*;
+#[cfg(feature = "std")]
 use std::io::{Error, ErrorKind};
-use std::ops::Add;
-use std::collections::HashMap;
-use std::hash::Hash;
 
-#[derive(Debug, PartialEq, Eq, Clone)]
-pub enum HuffTree<V: Eq + Copy> {
-    Leaf(V),
-    Node(Box<HuffTree<V>>, Box<HuffTree<V>>),
-}
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HuffTree<V: Eq + Clone> {
+    Leaf(V),
+    Node(Box<HuffTree<V>>, Box<HuffTree<V>>),
+}
+
+// the deepest a tree is dropped without being reshaped first, see
+// `HuffTree::drop_deep`
+const DROP_DEPTH: usize = 64;
+
+impl<V: Eq + Clone> HuffTree<V> {
+    fn is_node(&self) -> bool {
+        match *self {
+            HuffTree::Leaf(_) => false,
+            HuffTree::Node(..) => true,
+        }
+    }
+
+    // Reshapes a deep tree into a balanced one, so dropping it does not
+    // recurse once per level. Rotations only swap children around, so this
+    // takes no allocations and no copies of symbols.
+    fn balance_for_drop(&mut self) {
+        if self.deeper_than(DROP_DEPTH) {
+            let len = self.make_vine();
+            self.fold_vine(len);
+        }
+    }
+
+    // whether any leaf is more than `depth` levels down, looking no deeper
+    // than that
+    fn deeper_than(&self, depth: usize) -> bool {
+        match *self {
+            HuffTree::Leaf(_) => false,
+            HuffTree::Node(..) if depth == 0 => true,
+            HuffTree::Node(ref l, ref r) => l.deeper_than(depth - 1) || r.deeper_than(depth - 1),
+        }
+    }
+
+    // `(a, (b, c))` to `((a, b), c)`, leaving leaves in place
+    fn rotate_left(&mut self) {
+        if let HuffTree::Node(ref mut a, ref mut r) = *self {
+            if let HuffTree::Node(ref mut b, ref mut c) = **r {
+                mem::swap(b, c);
+                mem::swap(b, a);
+            }
+        }
+        if let HuffTree::Node(ref mut l, ref mut r) = *self {
+            mem::swap(l, r);
+        }
+    }
+
+    // `((a, b), c)` to `(a, (b, c))`, the reverse of `rotate_left`
+    fn rotate_right(&mut self) {
+        if let HuffTree::Node(ref mut l, ref mut c) = *self {
+            if let HuffTree::Node(ref mut a, ref mut b) = **l {
+                mem::swap(a, b);
+                mem::swap(b, c);
+            }
+        }
+        if let HuffTree::Node(ref mut l, ref mut r) = *self {
+            mem::swap(l, r);
+        }
+    }
+
+    // Rotates every node onto the right edge, leaving a leaf to the left of
+    // each, and returns how many nodes there are.
+    fn make_vine(&mut self) -> usize {
+        let mut len = 0;
+        let mut cursor = self;
+        loop {
+            while let HuffTree::Node(ref l, _) = *cursor {
+                if !l.is_node() {
+                    break;
+                }
+                cursor.rotate_right();
+            }
+
+            cursor = match *cursor {
+                HuffTree::Node(_, ref mut r) => r,
+                HuffTree::Leaf(_) => return len,
+            };
+            len += 1;
+        }
+    }
+
+    // Pairs up neighbouring subtrees along a vine of `len` nodes until one
+    // node is left, halving its length and adding a level each time.
+    fn fold_vine(&mut self, mut len: usize) {
+        while len > 1 {
+            let mut cursor = &mut *self;
+            for _ in 0..len / 2 {
+                cursor.rotate_left();
+                cursor = match *cursor {
+                    HuffTree::Node(_, ref mut r) => r,
+                    HuffTree::Leaf(_) => unreachable!("a vine node has a right child"),
+                };
+            }
+            len -= len / 2;
+        }
+    }
+}
+
+impl<V: Eq + Clone> HuffTree<V> {
+    pub fn new_leaf(value: V) -> Self {
+        HuffTree::Leaf(value)
+    }
+
+    pub fn new_node(left: Self, right: Self) -> Self {
+        HuffTree::Node(Box::new(left), Box::new(right))
+    }
+
+    pub fn contains(&self, value: &V) -> bool {
+        self.leaves().any(|(v, _)| v == value)
+    }
+
+    /// Rewrites every leaf value through `f`, keeping the shape of the tree
+    /// and so every code.
+    ///
+    /// `f` is called on the leaves from left to right. If it maps two
+    /// symbols to the same value, the new tree will not `validate`.
+    pub fn map_symbols<U: Eq + Clone, F: FnMut(V) -> U>(self, mut f: F) -> HuffTree<U> {
+        self.map_with(&mut f)
+    }
+
+    fn map_with<U: Eq + Clone, F: FnMut(V) -> U>(self, f: &mut F) -> HuffTree<U> {
+        self.fold(|v| HuffTree::Leaf(f(v)), HuffTree::new_node)
+    }
+
+    // Takes the tree apart from the bottom up, turning each leaf into a `T`
+    // with `leaf` and combining the two halves of each node with `node`.
+    // Leaves are visited from left to right, and the walk keeps a stack of
+    // its own so a deep tree does not recurse once per level.
+    fn fold<T, L, N>(self, mut leaf: L, mut node: N) -> T
+    where
+        L: FnMut(V) -> T,
+        N: FnMut(T, T) -> T,
+    {
+        // `None` marks where the two halves of a node are to be combined
+        let mut todo = vec![Some(self)];
+        let mut done: Vec<T> = vec![];
+        while let Some(step) = todo.pop() {
+            match step {
+                Some(HuffTree::Leaf(v)) => done.push(leaf(v)),
+                Some(HuffTree::Node(l, r)) => {
+                    todo.push(None);
+                    todo.push(Some(*r));
+                    todo.push(Some(*l));
+                }
+                None => {
+                    let r = done.pop().expect("a node has a right half");
+                    let l = done.pop().expect("a node has a left half");
+                    done.push(node(l, r));
+                }
+            }
+        }
+
+        done.pop().expect("a tree has a root")
+    }
+
+    // Each leaf's symbol with its depth, from left to right, walked with a
+    // stack rather than recursing.
+    fn leaves(&self) -> impl Iterator<Item = (&V, usize)> + '_ {
+        let mut stack = vec![(self, 0)];
+        core::iter::from_fn(move || loop {
+            let (tree, depth) = stack.pop()?;
+            match *tree {
+                HuffTree::Leaf(ref v) => return Some((v, depth)),
+                HuffTree::Node(ref l, ref r) => {
+                    stack.push((r, depth + 1));
+                    stack.push((l, depth + 1));
+                }
+            }
+        })
+    }
+
+    /// Puts `left` and `right` under a new root, prefixing their codes with
+    /// `0` and `1`.
+    ///
+    /// This is `new_node` under a name for combining whole trees. Nothing
+    /// checks that the trees share no symbols, see `try_join` for that.
+    pub fn join(left: Self, right: Self) -> Self {
+        HuffTree::new_node(left, right)
+    }
+
+    /// Drops the tree without recursing once per level, as dropping it the
+    /// usual way does.
+    ///
+    /// Meant for trees thousands of levels deep, such as ones read from
+    /// untrusted input, that could otherwise overflow the stack. A deep tree
+    /// is first rebalanced in place, which takes no allocations, and a tree
+    /// of a usual depth is dropped as it is. Readers drop the tree they hold
+    /// this way.
+    pub fn drop_deep(mut self) {
+        self.balance_for_drop();
+    }
+
+    /// Removes the leaf for `sym`, putting its sibling in place of their
+    /// parent, or `None` if no symbols would be left.
+    ///
+    /// Every symbol under the sibling gets a code one bit shorter, so data
+    /// coded with the old tree cannot be decoded with the new one. A tree
+    /// without `sym` is returned as it is.
+    pub fn prune(self, sym: &V) -> Option<Self> {
+        self.fold(
+            |v| if v == *sym { None } else { Some(HuffTree::Leaf(v)) },
+            |l, r| match (l, r) {
+                (Some(l), Some(r)) => Some(HuffTree::new_node(l, r)),
+                (Some(tree), None) | (None, Some(tree)) => Some(tree),
+                (None, None) => None,
+            },
+        )
+    }
+
+    /// Builds a balanced tree over `symbols`, ignoring how often they occur,
+    /// as a fixed length code to compare against or fall back on.
+    ///
+    /// With a power of two number of symbols every code is `log2(n)` bits.
+    /// Otherwise the tree is split as evenly as it can be, giving codes of
+    /// `floor(log2(n))` and `ceil(log2(n))` bits, since every node has two
+    /// children. Symbols keep their order from left to right. `None` if
+    /// there are no symbols. Nothing checks that the symbols are distinct.
+    pub fn fixed<I: IntoIterator<Item = V>>(symbols: I) -> Option<Self> {
+        fn split<V: Eq + Clone>(mut symbols: Vec<V>) -> HuffTree<V> {
+            if symbols.len() == 1 {
+                return HuffTree::new_leaf(symbols.pop().unwrap());
+            }
+
+            let right = symbols.split_off(symbols.len() / 2);
+            HuffTree::new_node(split(symbols), split(right))
+        }
+
+        let symbols: Vec<V> = symbols.into_iter().collect();
+        if symbols.is_empty() {
+            return None;
+        }
+
+        Some(split(symbols))
+    }
+
+    /// All symbols in the tree, in left to right order.
+    pub fn symbols(&self) -> Vec<V> {
+        self.leaves().map(|(v, _)| v.clone()).collect()
+    }
+
+    /// Each symbol with its code, in left to right order as in `symbols`.
+    ///
+    /// Codes are the same as in `encoding`, but found one at a time as the
+    /// iterator is walked, so they can be streamed out without building the
+    /// whole map.
+    pub fn codes(&self) -> impl Iterator<Item = (V, Vec<bool>)> + '_ {
+        // a lone leaf still has to put a bit on the wire, as in `encoding`
+        let root = match *self {
+            HuffTree::Leaf(_) => vec![false],
+            HuffTree::Node(..) => vec![],
+        };
+
+        // subtrees still to visit with their codes, the next on top
+        let mut stack = vec![(self, root)];
+        core::iter::from_fn(move || loop {
+            let (tree, code) = stack.pop()?;
+            match *tree {
+                HuffTree::Leaf(ref v) => return Some((v.clone(), code)),
+                HuffTree::Node(ref l, ref r) => {
+                    let mut right = code.clone();
+                    right.push(true);
+                    stack.push((r, right));
+
+                    let mut left = code;
+                    left.push(false);
+                    stack.push((l, left));
+                }
+            }
+        })
+    }
+
+    /// Each symbol with its code packed into a `u32`, first bit most
+    /// significant, and the code's length, in the order of `codes`.
+    ///
+    /// Meant for decoders and bindings that work with codes as integers.
+    /// Fails with `TreeError::CodeTooLong` if any code is longer than 32
+    /// bits.
+    pub fn packed_codes(&self) -> Result<Vec<(V, u32, u8)>, TreeError<V>> {
+        if self.max_code_length() > 32 {
+            return Err(TreeError::CodeTooLong(32));
+        }
+
+        Ok(self
+            .codes()
+            .map(|(v, code)| {
+                let bits = code.iter().fold(0, |bits, &bit| bits << 1 | bit as u32);
+                (v, bits, code.len() as u8)
+            })
+            .collect())
+    }
+
+    /// Number of `Leaf`s in the tree, one for each symbol.
+    pub fn count_leaves(&self) -> usize {
+        self.leaves().count()
+    }
+
+    /// Number of `Node`s in the tree, which as every node has two children
+    /// is one less than `count_leaves`.
+    ///
+    /// `serialize` writes one shape bit for each leaf and node.
+    pub fn count_nodes(&self) -> usize {
+        self.count_leaves() - 1
+    }
+
+    /// Length of the longest code in the tree.
+    ///
+    /// A lone leaf has a one bit code, see `encoding`.
+    pub fn max_code_length(&self) -> usize {
+        match *self {
+            HuffTree::Leaf(_) => 1,
+            HuffTree::Node(..) => self.depth(core::cmp::max),
+        }
+    }
+
+    /// Length of the shortest code in the tree.
+    pub fn min_code_length(&self) -> usize {
+        match *self {
+            HuffTree::Leaf(_) => 1,
+            HuffTree::Node(..) => self.depth(core::cmp::min),
+        }
+    }
+
+    /// How far the tree is from balanced, as the longest code length over
+    /// the shortest.
+    ///
+    /// A balanced tree gives `1.0`, or at most `2.0` for a symbol count that
+    /// is not a power of two, while a skewed weight distribution gives
+    /// a long tail of deep codes and a much larger ratio. `depth_histogram`
+    /// shows where the lengths lie in between.
+    pub fn imbalance(&self) -> f64 {
+        self.max_code_length() as f64 / self.min_code_length() as f64
+    }
+
+    /// Number of symbols with each code length, indexed by length.
+    ///
+    /// A lone leaf is counted with a length of one, as in `max_code_length`.
+    pub fn depth_histogram(&self) -> Vec<usize> {
+        let mut lengths = vec![];
+        self.collect_lengths(0, &mut lengths);
+
+        let mut histogram = vec![0; self.max_code_length() + 1];
+        for (_, len) in lengths {
+            histogram[len] += 1;
+        }
+
+        histogram
+    }
+
+    /// Each symbol with the length of its code, in left to right order as in
+    /// `symbols`.
+    ///
+    /// This is all `from_code_lengths` needs to rebuild the canonical codes.
+    /// `None` if any code is longer than 255 bits.
+    pub fn code_lengths(&self) -> Option<Vec<(V, u8)>> {
+        let mut lengths = vec![];
+        self.collect_lengths(0, &mut lengths);
+
+        lengths
+            .into_iter()
+            .map(|(v, len)| if len <= 255 { Some((v, len as u8)) } else { None })
+            .collect()
+    }
+
+    // the depth of the leaf `pick` prefers
+    fn depth<F: Fn(usize, usize) -> usize>(&self, pick: F) -> usize {
+        self.leaves().map(|(_, depth)| depth).fold(None, |picked, depth| {
+            Some(picked.map_or(depth, |picked| pick(picked, depth)))
+        }).unwrap_or(0)
+    }
+
+    fn collect_lengths(&self, depth: usize, lengths: &mut Vec<(V, usize)>) {
+        for (v, below) in self.leaves() {
+            lengths.push((v.clone(), core::cmp::max(depth + below, 1)));
+        }
+    }
+
+    // Builds the tree whose codes have the given lengths, handing out the
+    // shortest codes first. `None` if the lengths do not form a complete
+    // prefix code.
+    fn from_lengths(mut lengths: Vec<(V, usize)>) -> Option<Self> {
+        if lengths.len() == 1 {
+            return lengths.pop().map(|(v, _)| HuffTree::new_leaf(v));
+        }
+
+        lengths.sort_by_key(|&(_, len)| len);
+
+        // a complete code over `n` symbols has no code longer than `n - 1`
+        // bits, so no more levels than that are ever allocated
+        let max_len = lengths.last()?.1;
+        if lengths[0].1 == 0 || max_len >= lengths.len() {
+            return None;
+        }
+
+        let mut counts = vec![0usize; max_len + 1];
+        for &(_, len) in &lengths {
+            counts[len] += 1;
+        }
+
+        // the Kraft sum is exactly one when, from the longest codes up, the
+        // codes of each length pair off into the level above, leaving the two
+        // halves of the root
+        let mut carry = 0;
+        for &count in counts[1..].iter().rev() {
+            let total = count + carry;
+            if total % 2 != 0 {
+                return None;
+            }
+            carry = total / 2;
+        }
+        if carry != 1 {
+            return None;
+        }
+
+        // built from the bottom up, so deep codes do not recurse; at each
+        // level the leaves take the leftmost places, as the shortest codes
+        let mut leaves: Vec<Vec<V>> = vec![vec![]; max_len + 1];
+        for (v, len) in lengths {
+            leaves[len].push(v);
+        }
+
+        let mut level: Vec<HuffTree<V>> = vec![];
+        for depth in (1..=max_len).rev() {
+            let mut above: Vec<HuffTree<V>> =
+                leaves[depth].drain(..).map(HuffTree::new_leaf).collect();
+
+            let mut below = level.into_iter();
+            while let (Some(l), Some(r)) = (below.next(), below.next()) {
+                above.push(HuffTree::new_node(l, r));
+            }
+            level = above;
+        }
+
+        let mut level = level.into_iter();
+        match (level.next(), level.next()) {
+            (Some(l), Some(r)) => Some(HuffTree::new_node(l, r)),
+            _ => None,
+        }
+    }
+
+    /// Draws the tree as indented branches, one line per node, with each
+    /// leaf's value after its code.
+    ///
+    /// ```text
+    /// *
+    /// |-- 0: 'd'
+    /// `-- 1
+    ///     |-- 10: 'b'
+    ///     `-- 11: 'a'
+    /// ```
+    ///
+    /// This is meant for reading small trees: it recurses once per level,
+    /// and each line is as long as its code, so a tree thousands of levels
+    /// deep can overflow the stack.
+    pub fn to_ascii_tree(&self) -> String
+    where
+        V: Debug,
+    {
+        let mut out = String::new();
+        match *self {
+            HuffTree::Leaf(ref v) => out.push_str(&format!("0: {:?}\n", v)),
+            HuffTree::Node(ref l, ref r) => {
+                out.push_str("*\n");
+                let mut code = String::new();
+                l.draw(&mut out, "", &mut code, false);
+                r.draw(&mut out, "", &mut code, true);
+            }
+        }
+
+        out
+    }
+
+    fn draw(&self, out: &mut String, indent: &str, code: &mut String, right: bool)
+    where
+        V: Debug,
+    {
+        code.push(if right { '1' } else { '0' });
+        out.push_str(indent);
+        out.push_str(if right { "`-- " } else { "|-- " });
+
+        match *self {
+            HuffTree::Leaf(ref v) => out.push_str(&format!("{}: {:?}\n", code, v)),
+            HuffTree::Node(ref l, ref r) => {
+                out.push_str(code);
+                out.push('\n');
+
+                let indent = format!("{}{}", indent, if right { "    " } else { "|   " });
+                l.draw(out, &indent, code, false);
+                r.draw(out, &indent, code, true);
+            }
+        }
+
+        code.pop();
+    }
+
+    #[cfg(feature = "std")]
+    pub fn decode_all<R: Read>(&self, reader: R, count: usize) -> std::io::Result<Vec<V>> {
+        let mut reader = BitReader::new(reader);
+        let mut output = Vec::with_capacity(count);
+
+        while output.len() < count {
+            match self.decode_next(&mut reader)? {
+                Some(value) => output.push(value),
+                None => break,
+            }
+        }
+
+        Ok(output)
+    }
+
+    #[cfg(feature = "std")]
+    fn decode_next<R: Read>(&self, reader: &mut BitReader<R>) -> std::io::Result<Option<V>> {
+        self.decode_with(|| reader.read_bit())
+    }
+
+    // Walks the tree from the root taking bits from `next_bit`, returning
+    // `None` if the bits end before the first one and `UnexpectedEof` if they
+    // end part way down.
+    #[cfg(feature = "std")]
+    fn decode_with<F>(&self, mut next_bit: F) -> std::io::Result<Option<V>>
+    where
+        F: FnMut() -> std::io::Result<Option<bool>>,
+    {
+        // a lone leaf is encoded as a single `0` bit, see `encoding`
+        if let HuffTree::Leaf(ref value) = *self {
+            return match next_bit()? {
+                Some(false) => Ok(Some(value.clone())),
+                Some(true) => Err(HuffError::InvalidCode.into()),
+                None => Ok(None),
+            };
+        }
+
+        let mut cursor = self;
+        let mut consumed = false;
+
+        loop {
+            match *cursor {
+                HuffTree::Leaf(ref value) => return Ok(Some(value.clone())),
+                HuffTree::Node(ref l, ref r) => {
+                    match next_bit()? {
+                        Some(b) => {
+                            cursor = if b { r } else { l };
+                            consumed = true;
+                        }
+                        None if consumed => return Err(HuffError::UnexpectedEof.into()),
+                        None => return Ok(None),
+                    }
+                }
+            };
+        }
+    }
+}
+
+impl<V: Eq + Clone + MapKey> HuffTree<V> {
+    pub fn encoding(self) -> Map<V, Vec<bool>> {
+        self.encoding_ref()
+    }
+
+    /// Same as `encoding`, but leaves the tree in place for decoding.
+    pub fn encoding_ref(&self) -> Map<V, Vec<bool>> {
+        let trail: Vec<bool> = vec![];
+        let mut map = Map::new();
+
+        match *self {
+            // a lone leaf still has to put a bit on the wire
+            HuffTree::Leaf(ref v) => {
+                map.insert(v.clone(), vec![false]);
+            }
+            ref tree => tree.build_map(trail, &mut map),
+        }
+
+        map
+    }
+
+    /// Encodes `data` into packed bytes, also returning the exact number of
+    /// bits written so the padding in the last byte can be told apart.
+    #[cfg(feature = "std")]
+    pub fn encode_slice(&self, data: &[V]) -> std::io::Result<(Vec<u8>, usize)>
+    where
+        V: Debug,
+    {
+        let encoding = self.encoding_ref();
+        let mut bit_len = 0;
+
+        let mut writer = BitWriter::new(vec![]);
+        for value in data {
+            let bits = match encoding.get(value) {
+                Some(bits) => bits,
+                None => return Err(unknown_symbol(value)),
+            };
+
+            for bit in bits {
+                writer.write_bit(*bit)?;
+            }
+            bit_len += bits.len();
+        }
+
+        Ok((writer.finish()?, bit_len))
+    }
+
+    /// Checks that every symbol appears in only one leaf, failing with the
+    /// first symbol found twice in left to right order.
+    ///
+    /// Every node has two children, so the codes always form a complete
+    /// prefix code and meet the Kraft inequality with equality. Duplicate
+    /// symbols are the one way a hand built tree can give a broken
+    /// `encoding`.
+    pub fn validate(&self) -> Result<(), TreeError<V>> {
+        let mut seen = Set::new();
+        for v in self.symbols() {
+            if seen.contains(&v) {
+                return Err(TreeError::DuplicateSymbol(v));
+            }
+            seen.insert(v);
+        }
+
+        Ok(())
+    }
+
+    /// Whether both trees give every symbol a code of the same length,
+    /// however the codes themselves are arranged.
+    ///
+    /// Trees that differ only in which child of a node is left and which is
+    /// right code data into the same number of bits, and give the same
+    /// `canonical_encoding`, so this is the equality to test a builder
+    /// against without pinning down how it breaks ties.
+    pub fn same_codes(&self, other: &HuffTree<V>) -> bool {
+        let mut lengths = vec![];
+        self.collect_lengths(0, &mut lengths);
+        let lengths: Map<V, usize> = lengths.into_iter().collect();
+
+        let mut other_lengths = vec![];
+        other.collect_lengths(0, &mut other_lengths);
+        let other_lengths: Map<V, usize> = other_lengths.into_iter().collect();
+
+        lengths == other_lengths
+    }
+
+    /// Joins two trees as `join` does, first checking that no symbol is in
+    /// both.
+    ///
+    /// Fails with `TreeError::DuplicateSymbol` naming the first symbol of
+    /// `right` that is also in `left`. Symbols repeated within one of the
+    /// trees are not looked for, see `validate`.
+    pub fn try_join(left: Self, right: Self) -> Result<Self, TreeError<V>> {
+        let mut seen = Set::new();
+        left.for_each_symbol(&mut |v| {
+            seen.insert(v.clone());
+        });
+
+        let mut shared = None;
+        right.for_each_symbol(&mut |v| {
+            if shared.is_none() && seen.contains(v) {
+                shared = Some(v.clone());
+            }
+        });
+
+        match shared {
+            Some(v) => Err(TreeError::DuplicateSymbol(v)),
+            None => Ok(HuffTree::join(left, right)),
+        }
+    }
+
+    fn for_each_symbol<F: FnMut(&V)>(&self, f: &mut F) {
+        for (v, _) in self.leaves() {
+            f(v);
+        }
+    }
+
+    /// Number of bits `data` encodes to, without any padding.
+    ///
+    /// This builds the encoding map on each call, `HuffWriter::encoded_bit_len`
+    /// reuses the writer's map instead.
+    #[cfg(feature = "std")]
+    pub fn encoded_bit_len(&self, data: &[V]) -> std::io::Result<usize>
+    where
+        V: Debug,
+    {
+        let encoding = self.encoding_ref();
+        sum_code_lengths(data, |value| encoding.get(value).map(Vec::len))
+    }
+
+    /// Average number of bits per symbol when coding data where each symbol
+    /// occurs with the given weight.
+    ///
+    /// Symbols in the tree that are missing from `weights` count as weight
+    /// zero. Returns `0.0` if the total weight is zero.
+    pub fn average_code_length<W>(&self, weights: &Map<V, W>) -> f64
+    where
+        W: Into<f64> + Clone,
+    {
+        let mut lengths = vec![];
+        self.collect_lengths(0, &mut lengths);
+
+        let mut total_weight = 0.0;
+        let mut total_bits = 0.0;
+
+        for (v, len) in lengths {
+            if let Some(weight) = weights.get(&v) {
+                let weight: f64 = weight.clone().into();
+                total_weight += weight;
+                total_bits += weight * len as f64;
+            }
+        }
+
+        if total_weight == 0.0 {
+            0.0
+        } else {
+            total_bits / total_weight
+        }
+    }
+
+    /// The fraction of bits saved coding data where each symbol occurs with
+    /// the given weight, against a fixed length code of `ceil(log2(n))` bits
+    /// for the `n` symbols in the tree.
+    ///
+    /// `0.25` means a quarter fewer bits than the fixed code, and a negative
+    /// result means more. Weights are taken as in `average_code_length`,
+    /// and `0.0` is returned if the total weight is zero.
+    pub fn savings_vs_fixed<W>(&self, weights: &Map<V, W>) -> f64
+    where
+        W: Into<f64> + Clone,
+    {
+        let average = self.average_code_length(weights);
+        if average == 0.0 {
+            return 0.0;
+        }
+
+        // a lone symbol still takes a bit, as in `encoding`
+        let symbols = self.count_leaves();
+        let fixed = core::cmp::max(1, usize::BITS - (symbols - 1).leading_zeros());
+
+        1.0 - average / f64::from(fixed)
+    }
+
+    /// How many bits per symbol coding with the tree takes over the
+    /// entropy of the data, `average_code_length` less `shannon_entropy`.
+    ///
+    /// Zero means no code could do better, and a Huffman tree built from
+    /// `weights` stays under one bit. `weights` should only hold symbols in
+    /// the tree, since the others count towards the entropy but not the
+    /// code length.
+    #[cfg(feature = "std")]
+    pub fn redundancy<W>(&self, weights: &Map<V, W>) -> f64
+    where
+        W: Into<f64> + Clone,
+    {
+        self.average_code_length(weights) - shannon_entropy(weights)
+    }
+
+    /// Measures the tree against the data it was built for, where each
+    /// symbol occurs with the given weight.
+    ///
+    /// Symbols in the tree that are missing from `weights` count as weight
+    /// zero, and symbols in `weights` that are not in the tree are ignored.
+    #[cfg(feature = "std")]
+    pub fn weight_of<W>(&self, weights: &Map<V, W>) -> TreeStats
+    where
+        W: Into<f64> + Clone,
+    {
+        let mut lengths = vec![];
+        self.collect_lengths(0, &mut lengths);
+
+        let symbols = lengths.len();
+        let weighted: Vec<(f64, usize)> = lengths
+            .into_iter()
+            .filter_map(|(v, len)| weights.get(&v).map(|w| (w.clone().into(), len)))
+            .collect();
+
+        let total_weight: f64 = weighted.iter().map(|&(w, _)| w).sum();
+        let mut stats = TreeStats {
+            total_bits: 0.0,
+            entropy_bits: 0.0,
+            symbols,
+        };
+
+        for (weight, len) in weighted {
+            if weight > 0.0 {
+                stats.total_bits += weight * len as f64;
+                stats.entropy_bits += weight * (total_weight / weight).log2();
+            }
+        }
+
+        stats
+    }
+
+    // Walks the tree with a stack of its own rather than recursing, so a
+    // deep tree cannot overflow the call stack.
+    fn build_map(&self, trail: Vec<bool>, map: &mut Map<V, Vec<bool>>) {
+        let mut stack = vec![(self, trail)];
+        while let Some((tree, trail)) = stack.pop() {
+            match *tree {
+                HuffTree::Leaf(ref v) => {
+                    map.insert(v.clone(), trail);
+                }
+                HuffTree::Node(ref l, ref r) => {
+                    let mut left = trail.clone();
+                    left.push(false);
+                    stack.push((l, left));
+
+                    let mut right = trail;
+                    right.push(true);
+                    stack.push((r, right));
+                }
+            }
+        }
+    }
+}
+
+impl<V: Eq + Clone + MapKey + Ord> HuffTree<V> {
+    /// Assigns canonical codes with the same lengths as `encoding`.
+    ///
+    /// Symbols are ordered by code length and then by value, and each gets
+    /// the next code in sequence, as in DEFLATE and JPEG.
+    pub fn canonical_encoding(&self) -> Map<V, Vec<bool>> {
+        let mut lengths = vec![];
+        self.collect_lengths(0, &mut lengths);
+
+        lengths.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+
+        let mut map = Map::new();
+        let mut code: Vec<bool> = vec![];
+
+        for (v, len) in lengths {
+            code.resize(len, false);
+            map.insert(v, code.clone());
+
+            // step to the next code, clearing trailing ones and setting the last zero
+            while code.last() == Some(&true) {
+                code.pop();
+            }
+            if let Some(bit) = code.last_mut() {
+                *bit = true;
+            }
+        }
+
+        map
+    }
+
+    /// The codes of `encoding` ordered by code length and then by symbol,
+    /// the order `canonical_encoding` hands out codes in.
+    ///
+    /// Unlike the map, the order does not change from run to run, so headers
+    /// written from it are the same byte for byte.
+    pub fn sorted_encoding(&self) -> Vec<(V, Vec<bool>)> {
+        let mut codes: Vec<(V, Vec<bool>)> = self.encoding_ref().into_iter().collect();
+        codes.sort_by(|a, b| a.1.len().cmp(&b.1.len()).then(a.0.cmp(&b.0)));
+
+        codes
+    }
+
+    /// Builds the tree whose codes are the canonical codes for the given
+    /// lengths, so its `encoding` matches `canonical_encoding`.
+    ///
+    /// Symbols with a length of zero are left out, as DEFLATE does for
+    /// unused symbols. `None` if no symbols are left or the lengths do not
+    /// exactly fill the code space, either overflowing it against the Kraft
+    /// inequality or leaving codes unused.
+    pub fn from_code_lengths(lengths: &[(V, usize)]) -> Option<Self> {
+        let mut lengths: Vec<(V, usize)> = lengths
+            .iter()
+            .filter(|&&(_, len)| len > 0)
+            .cloned()
+            .collect();
+
+        // `from_lengths` keeps equal lengths in order, which with the values
+        // sorted hands out the canonical codes
+        lengths.sort_by(|a, b| a.0.cmp(&b.0));
+
+        HuffTree::from_lengths(lengths)
+    }
+
+    /// Rebuilds the tree so its `encoding` is the `canonical_encoding`,
+    /// keeping every code length.
+    ///
+    /// Trees with the same code lengths come out equal, so this normalizes
+    /// trees for comparison, and a canonical tree is fully described by its
+    /// `code_lengths`.
+    pub fn into_canonical(self) -> Self {
+        let mut lengths = vec![];
+        self.collect_lengths(0, &mut lengths);
+        lengths.sort_by(|a, b| a.0.cmp(&b.0));
+
+        HuffTree::from_lengths(lengths).expect("the codes of a tree fill the code space")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<V: Eq + Clone> HuffTree<V> {
+    /// Writes the tree shape followed by its leaf values.
+    ///
+    /// The shape is written as one bit per tree element in pre-order, `1` for
+    /// a node and `0` for a leaf, padded with `0` bits to a whole byte. The
+    /// leaf values then follow from left to right, each written by
+    /// `write_value`.
+    pub fn serialize_with<W, F>(&self, writer: &mut W, mut write_value: F) -> std::io::Result<()>
+    where
+        W: Write,
+        F: FnMut(&mut W, V) -> std::io::Result<()>,
+    {
+        let mut values = vec![];
+        {
+            let mut bits = BitWriter::new(&mut *writer);
+            self.write_shape(&mut bits, &mut values)?;
+            bits.finish()?;
+        }
+
+        for value in values {
+            write_value(writer, value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads back a tree written by `serialize_with`, reading each leaf value
+    /// with `read_value`.
+    pub fn deserialize_with<R, F>(reader: &mut R, mut read_value: F) -> std::io::Result<Self>
+    where
+        R: Read,
+        F: FnMut(&mut R) -> std::io::Result<V>,
+    {
+        let mut shape = vec![];
+        {
+            let mut bits = BitReader::new(&mut *reader);
+            // subtrees that are still to be read
+            let mut open = 1;
+
+            while open > 0 {
+                let bit = match bits.read_bit()? {
+                    Some(bit) => bit,
+                    None => return Err(HuffError::UnexpectedEof.into()),
+                };
+
+                if bit {
+                    open += 1;
+                } else {
+                    open -= 1;
+                }
+                shape.push(bit);
+            }
+        }
+
+        let mut values = vec![];
+        for _ in shape.iter().filter(|bit| !**bit) {
+            values.push(read_value(reader)?);
+        }
+
+        HuffTree::from_shape(shape, values)
+    }
+
+    // Rebuilds the tree from its shape bits in pre-order, keeping the nodes
+    // still waiting on a child on a stack rather than recursing, so a deep
+    // tree from untrusted input cannot overflow the call stack.
+    fn from_shape(shape: Vec<bool>, values: Vec<V>) -> std::io::Result<Self> {
+        // open nodes, each with its left child once that is complete
+        let mut open = vec![];
+        let result = HuffTree::fold_shape(shape, values, &mut open);
+
+        // the subtrees left open by an error may be deep too
+        for tree in open.into_iter().flatten() {
+            tree.drop_deep();
+        }
+
+        result
+    }
+
+    fn fold_shape(
+        shape: Vec<bool>,
+        values: Vec<V>,
+        open: &mut Vec<Option<HuffTree<V>>>,
+    ) -> std::io::Result<Self> {
+        // a tree with `n` leaves is never more than `n - 1` nodes deep
+        let max_depth = values.len().saturating_sub(1);
+        let mut values = values.into_iter();
+
+        for bit in shape {
+            if bit {
+                if open.len() == max_depth {
+                    let msg = "tree shape is deeper than its number of leaves allows";
+                    return Err(Error::new(ErrorKind::InvalidData, msg));
+                }
+                open.push(None);
+                continue;
+            }
+
+            let mut done = match values.next() {
+                Some(v) => HuffTree::new_leaf(v),
+                None => return Err(HuffError::UnexpectedEof.into()),
+            };
+            loop {
+                match open.pop() {
+                    None => return Ok(done),
+                    Some(None) => {
+                        open.push(Some(done));
+                        break;
+                    }
+                    Some(Some(left)) => done = HuffTree::new_node(left, done),
+                }
+            }
+        }
+
+        Err(HuffError::UnexpectedEof.into())
+    }
+
+    fn write_shape<W: Write>(
+        &self,
+        bits: &mut BitWriter<W>,
+        values: &mut Vec<V>,
+    ) -> std::io::Result<()> {
+        let mut stack = vec![self];
+        while let Some(tree) = stack.pop() {
+            match *tree {
+                HuffTree::Leaf(ref v) => {
+                    values.push(v.clone());
+                    bits.write_bit(false)?;
+                }
+                HuffTree::Node(ref l, ref r) => {
+                    bits.write_bit(true)?;
+                    stack.push(r);
+                    stack.push(l);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<V: Eq + Clone + Into<u8> + From<u8>> HuffTree<V> {
+    /// Writes the tree as described in `serialize_with`, with each leaf value
+    /// taking a single byte.
+    pub fn serialize<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.serialize_with(writer, |w, v| w.write_all(&[v.into()]))
+    }
+
+    pub fn deserialize<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        HuffTree::deserialize_with(reader, |r| {
+            let mut byte = [0];
+            r.read_exact(&mut byte)?;
+            Ok(V::from(byte[0]))
+        })
+    }
+}
+
+/// How well a tree codes the data it was built for, from
+/// `HuffTree::weight_of`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TreeStats {
+    /// Bits taken to code the data: each symbol's weight times its code
+    /// length, summed.
+    pub total_bits: f64,
+    /// The entropy of the data in bits, the least any prefix code could
+    /// take. For a Huffman tree `total_bits` is less than one bit per coded
+    /// symbol above it.
+    pub entropy_bits: f64,
+    /// Number of symbols in the tree.
+    pub symbols: usize,
+}
+
+/// Shannon entropy in bits per symbol of data where each symbol occurs with
+/// the given weight, the fewest bits per symbol any code could average.
+///
+/// Symbols of weight zero add nothing. Returns `0.0` if the total weight is
+/// zero.
+#[cfg(feature = "std")]
+pub fn shannon_entropy<V, W>(weights: &Map<V, W>) -> f64
+where
+    W: Into<f64> + Clone,
+{
+    let weights: Vec<f64> = weights.values().map(|w| w.clone().into()).collect();
+    let total: f64 = weights.iter().sum();
+
+    weights
+        .into_iter()
+        .filter(|&w| w > 0.0)
+        .map(|w| w / total * (total / w).log2())
+        .sum()
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum BuildError {
+    Empty,
+    WeightOverflow,
+    InvalidWeight,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BuildError::Empty => write!(f, "no symbols to build a tree from"),
+            BuildError::WeightOverflow => write!(f, "sum of weights overflowed"),
+            BuildError::InvalidWeight => write!(f, "a weight does not compare equal to itself"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BuildError {}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TreeError<V> {
+    DuplicateSymbol(V),
+    /// A code is longer than the given number of bits.
+    CodeTooLong(usize),
+}
+
+impl<V: Debug> fmt::Display for TreeError<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TreeError::DuplicateSymbol(ref v) => write!(f, "symbol {:?} is in the tree twice", v),
+            TreeError::CodeTooLong(max) => write!(f, "tree has a code longer than {} bits", max),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<V: Debug> std::error::Error for TreeError<V> {}
+
+/// The errors behind the `io::Error`s returned by readers and writers.
+///
+/// They are wrapped in an `io::Error` of the matching kind, and can be
+/// recovered from it with `get_ref` and `downcast_ref`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum HuffError {
+    /// A symbol not in the tree, as written by its `Debug` impl.
+    UnknownSymbol(String),
+    /// The symbol at the given index of a slice is not in the tree.
+    UnknownSymbolAt(usize),
+    /// The input ended part way through a symbol.
+    UnexpectedEof,
+    /// The input holds bits that are not a code of the tree.
+    InvalidCode,
+    /// No end of stream symbol was set.
+    NoEofSymbol,
+    /// A tree was asked for with no symbols to build it from.
+    EmptyAlphabet,
+    /// The sum of weights overflowed while building a tree.
+    WeightOverflow,
+    /// A weight could not be ordered against other weights, such as a `NaN`.
+    InvalidWeight,
+    /// The input holds more symbols than the given limit.
+    LimitExceeded(usize),
+    /// The tree has a code longer than the given number of bits.
+    CodeTooLong(usize),
+}
+
+impl fmt::Display for HuffError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HuffError::UnknownSymbol(ref v) => write!(f, "symbol {} is not in the tree", v),
+            HuffError::UnknownSymbolAt(i) => write!(f, "symbol at index {} is not in the tree", i),
+            HuffError::UnexpectedEof => write!(f, "input ended part way through a symbol"),
+            HuffError::InvalidCode => write!(f, "input is not a code of the tree"),
+            HuffError::NoEofSymbol => write!(f, "no end of stream symbol was set"),
+            HuffError::EmptyAlphabet => write!(f, "no symbols to build a tree from"),
+            HuffError::WeightOverflow => write!(f, "sum of weights overflowed"),
+            HuffError::InvalidWeight => write!(f, "a weight does not compare equal to itself"),
+            HuffError::LimitExceeded(max) => write!(f, "input holds more than {} symbols", max),
+            HuffError::CodeTooLong(max) => write!(f, "tree has a code longer than {} bits", max),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HuffError {}
+
+impl From<BuildError> for HuffError {
+    fn from(err: BuildError) -> Self {
+        match err {
+            BuildError::Empty => HuffError::EmptyAlphabet,
+            BuildError::WeightOverflow => HuffError::WeightOverflow,
+            BuildError::InvalidWeight => HuffError::InvalidWeight,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<HuffError> for Error {
+    fn from(err: HuffError) -> Self {
+        let kind = match err {
+            HuffError::UnknownSymbol(_) | HuffError::UnknownSymbolAt(_) => ErrorKind::InvalidInput,
+            HuffError::NoEofSymbol | HuffError::CodeTooLong(_) => ErrorKind::InvalidInput,
+            HuffError::UnexpectedEof => ErrorKind::UnexpectedEof,
+            HuffError::InvalidCode | HuffError::LimitExceeded(_) => ErrorKind::InvalidData,
+            HuffError::EmptyAlphabet | HuffError::WeightOverflow | HuffError::InvalidWeight => {
+                ErrorKind::InvalidInput
+            }
+        };
+
+        Error::new(kind, err)
+    }
+}
+
+/// How the weights of two nodes combine into the weight of their parent
+/// when building a tree.
+///
+/// Implemented as addition for the numeric types. Other weights can combine
+/// however they like, such as saturating or in the log domain, as long as
+/// the result orders consistently with `PartialOrd`.
+pub trait Combine {
+    /// The weight of a node with children weighing `self` and `other`.
+    fn combine(&self, other: &Self) -> Self;
+}
+
+macro_rules! combine_add {
+    ($($t:ty)*) => ($(
+        impl Combine for $t {
+            fn combine(&self, other: &Self) -> Self {
+                self + other
+            }
+        }
+    )*)
+}
+
+combine_add!(u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize f32 f64);
+
+/// Weights that can be summed without silently overflowing.
+pub trait CheckedAdd: Sized {
+    /// Sums the weights, `None` if the result cannot be represented.
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+}
+
+macro_rules! checked_add_int {
+    ($($t:ty)*) => ($(
+        impl CheckedAdd for $t {
+            fn checked_add(&self, other: &Self) -> Option<Self> {
+                <$t>::checked_add(*self, *other)
+            }
+        }
+    )*)
+}
+
+checked_add_int!(u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize);
+
+macro_rules! checked_add_float {
+    ($($t:ty)*) => ($(
+        impl CheckedAdd for $t {
+            fn checked_add(&self, other: &Self) -> Option<Self> {
+                let sum = self + other;
+                if sum.is_finite() {
+                    Some(sum)
+                } else {
+                    None
+                }
+            }
+        }
+    )*)
+}
+
+checked_add_float!(f32 f64);
+
+pub struct HuffBuilder<V: Eq + Clone, W: PartialOrd + Combine> {
+    nodes: Vec<(V, W)>,
+    // where each symbol passed to `observe` is in `nodes`
+    observed: Map<V, usize>,
+}
+
+impl<V: Eq + Clone, W: PartialOrd + Combine> HuffBuilder<V, W> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        HuffBuilder::with_capacity(0)
+    }
+
+    /// Creates a builder with room for `capacity` symbols before it has to
+    /// grow, for large alphabets added one symbol at a time.
+    pub fn with_capacity(capacity: usize) -> Self {
+        HuffBuilder {
+            nodes: Vec::with_capacity(capacity),
+            observed: Map::new(),
+        }
+    }
+
+    pub fn add(mut self, sym: V, weight: W) -> Self {
+        self.nodes.push((sym, weight));
+        self
+    }
+
+    /// Adds each symbol in `symbols` with the weight at the same index in
+    /// `weights`, for callers holding them in separate arrays.
+    ///
+    /// Panics if the slices differ in length.
+    pub fn add_parallel(mut self, symbols: &[V], weights: &[W]) -> Self
+    where
+        W: Clone,
+    {
+        assert_eq!(
+            symbols.len(),
+            weights.len(),
+            "{} symbols were given with {} weights",
+            symbols.len(),
+            weights.len()
+        );
+
+        self.nodes.reserve(symbols.len());
+        self.nodes.extend(symbols.iter().cloned().zip(weights.iter().cloned()));
+        self
+    }
+
+    /// Reserves `sym` as an end of stream marker.
+    ///
+    /// The symbol is added with a default, for numbers zero, weight so it
+    /// gets one of the longest codes; it is only written once per stream.
+    /// Pair it with `HuffWriter::with_eof` and `HuffReader::with_eof`.
+    pub fn with_eof(self, sym: V) -> Self
+    where
+        W: Default,
+    {
+        self.add(sym, W::default())
+    }
+}
+
+impl<V: Eq + Clone + Ord, W: PartialOrd + Combine> HuffBuilder<V, W> {
+    pub fn build(self) -> Option<HuffTree<V>> {
+        self.try_build().ok()
+    }
+
+    /// Builds the tree, failing with `BuildError::Empty` if no symbols were
+    /// added.
+    ///
+    /// A single symbol builds a lone leaf, which is given a one bit code by
+    /// `HuffTree::encoding`.
+    ///
+    /// A symbol added more than once becomes a single leaf with its weights
+    /// combined.
+    ///
+    /// The tree does not depend on the order symbols were added in. Symbols
+    /// of equal weight are ordered by value, and when a merged node ties in
+    /// weight with existing nodes the existing nodes are merged first.
+    ///
+    /// Symbols of weight zero are kept, with the longest codes. If every
+    /// weight is zero, or every weight is the same, all symbols tie and the
+    /// tree is balanced, with as many codes of each length as
+    /// `HuffTree::fixed` gives.
+    ///
+    /// Weights have to be ordered for the tree to be, so a weight that does
+    /// not compare equal to itself, such as a `NaN`, fails with
+    /// `BuildError::InvalidWeight`.
+    pub fn try_build(self) -> Result<HuffTree<V>, BuildError> {
+        self.build_with(|a, b| Some(a.combine(&b)))
+    }
+
+    /// Builds the tree as `try_build` does, but fails with
+    /// `BuildError::WeightOverflow` instead of overflowing when summing
+    /// weights.
+    pub fn build_checked(self) -> Result<HuffTree<V>, BuildError>
+    where
+        W: CheckedAdd,
+    {
+        self.build_with(|a, b| a.checked_add(&b))
+    }
+
+    /// Builds a balanced tree, with every code `log2(n)` bits, when the
+    /// number `n` of distinct symbols is a power of two, and the usual tree
+    /// of `build` otherwise.
+    ///
+    /// The balanced tree is `HuffTree::fixed` over the symbols sorted by
+    /// value, and does not look at weights at all, so it is built even for
+    /// weights `build` would reject. Any other number of symbols gets codes
+    /// from the weights exactly as `build` gives them, with no attempt at
+    /// keeping them close to equal length. `None` if no symbols were added.
+    pub fn build_uniform_if_pow2(self) -> Option<HuffTree<V>> {
+        let mut symbols: Vec<V> = self.nodes.iter().map(|(v, _)| v.clone()).collect();
+        symbols.sort();
+        symbols.dedup();
+
+        if symbols.len().is_power_of_two() {
+            return HuffTree::fixed(symbols);
+        }
+
+        self.build()
+    }
+
+    /// Keeps the `k` heaviest symbols and replaces the rest with `escape`,
+    /// weighing as much as all of them combined.
+    ///
+    /// This bounds the size of the tree for alphabets with a long tail of
+    /// rare symbols. The escape symbol only codes that some other symbol
+    /// follows: writing that symbol, for instance as raw bits after the
+    /// escape code, is left to the caller. No escape symbol is added if
+    /// there are no more than `k` symbols.
+    ///
+    /// Symbols are ranked as in `try_build`, so the symbols kept do not
+    /// depend on the order they were added in.
+    pub fn with_escape(self, k: usize, escape: V) -> Self {
+        let mut symbols = sorted_symbols(self.nodes, |a, b| Some(a.combine(&b)))
+            .expect("combining weights cannot fail");
+
+        if symbols.len() > k {
+            let mut tail = symbols.split_off(k).into_iter().map(|(_, w)| w);
+            let first = tail.next().unwrap();
+            let weight = tail.fold(first, |total, w| total.combine(&w));
+            symbols.push((escape, weight));
+        }
+
+        HuffBuilder {
+            nodes: symbols,
+            observed: Map::new(),
+        }
+    }
+
+    fn build_with<F>(self, mut add: F) -> Result<HuffTree<V>, BuildError>
+    where
+        F: FnMut(W, W) -> Option<W>,
+    {
+        if self.nodes.is_empty() {
+            return Err(BuildError::Empty);
+        }
+        check_weights(&self.nodes)?;
+
+        match sorted_symbols(self.nodes, &mut add) {
+            Some(symbols) => merge_sorted(symbols, add),
+            None => Err(BuildError::WeightOverflow),
+        }
+    }
+}
+
+// Fails if any weight is unordered even against itself, as a `NaN` is, which
+// would leave sorting and merging nodes without a consistent order.
+fn check_weights<V, W: PartialOrd>(nodes: &[(V, W)]) -> Result<(), BuildError> {
+    if nodes.iter().any(|(_, w)| w.partial_cmp(w).is_none()) {
+        return Err(BuildError::InvalidWeight);
+    }
+
+    Ok(())
+}
+
+// Coalesces repeated symbols, which would otherwise become leaves with the
+// same value, and sorts them by descending weight and then by value. `None`
+// if combining the weights of a repeated symbol fails.
+fn sorted_symbols<V, W, F>(mut nodes: Vec<(V, W)>, mut add: F) -> Option<Vec<(V, W)>>
+where
+    V: Ord,
+    W: PartialOrd,
+    F: FnMut(W, W) -> Option<W>,
+{
+    use core::cmp::Ordering;
+
+    nodes.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut symbols: Vec<(V, W)> = Vec::with_capacity(nodes.len());
+    for (v, w) in nodes {
+        match symbols.pop() {
+            Some((last, total)) if last == v => symbols.push((v, add(total, w)?)),
+            Some(last) => {
+                symbols.push(last);
+                symbols.push((v, w));
+            }
+            None => symbols.push((v, w)),
+        }
+    }
+
+    symbols.sort_by(|a, b| if b.1 > a.1 {
+        Ordering::Greater
+    } else if b.1 < a.1 {
+        Ordering::Less
+    } else {
+        a.0.cmp(&b.0)
+    });
+
+    Some(symbols)
+}
+
+impl<V: Eq + Clone, W: PartialOrd + Combine> HuffBuilder<V, W> {
+    /// Builds the tree from symbols that were added in order of descending
+    /// weight, skipping the sorting `try_build` does.
+    ///
+    /// Symbols added more than once are not coalesced, and symbols of equal
+    /// weight are taken in the order they were added. Debug builds panic if
+    /// the weights are out of order, while release builds give a tree of
+    /// unspecified shape, though still one with every symbol in it. A `NaN`
+    /// weight fails with `BuildError::InvalidWeight` as in `try_build`.
+    pub fn build_presorted(self) -> Result<HuffTree<V>, BuildError> {
+        use core::cmp::Ordering;
+
+        check_weights(&self.nodes)?;
+
+        debug_assert!(
+            self.nodes
+                .windows(2)
+                .all(|pair| pair[1].1.partial_cmp(&pair[0].1) != Some(Ordering::Greater)),
+            "symbols were not added in order of descending weight"
+        );
+
+        merge_sorted(self.nodes, |a, b| Some(a.combine(&b)))
+    }
+}
+
+// Builds a tree from symbols sorted by descending weight, repeatedly merging
+// the two lightest nodes.
+fn merge_sorted<V, W, F>(symbols: Vec<(V, W)>, mut add: F) -> Result<HuffTree<V>, BuildError>
+where
+    V: Eq + Clone,
+    W: PartialOrd,
+    F: FnMut(W, W) -> Option<W>,
+{
+    if symbols.is_empty() {
+        return Err(BuildError::Empty);
+    }
+
+    let mut nodes: Vec<(HuffTree<V>, W)> = symbols
+        .into_iter()
+        .map(|(v, w)| (HuffTree::new_leaf(v), w))
+        .collect();
+
+    while nodes.len() > 1 {
+        let (right_value, right_weight) = nodes.pop().unwrap();
+        let (left_value, left_weight) = nodes.pop().unwrap();
+
+        let new_weight = match add(left_weight, right_weight) {
+            Some(weight) => weight,
+            None => return Err(BuildError::WeightOverflow),
+        };
+
+        let node = HuffTree::new_node(left_value, right_value);
+
+        let pos = nodes.partition_point(|a| a.1 > new_weight);
+        nodes.insert(pos, (node, new_weight));
+    }
+
+    Ok(nodes.pop().unwrap().0)
+}
+
+/// Collects symbol and weight pairs into a builder, as `add_table` adds
+/// them.
+impl<V: Eq + Clone, W: PartialOrd + Combine> FromIterator<(V, W)> for HuffBuilder<V, W> {
+    fn from_iter<I: IntoIterator<Item = (V, W)>>(iter: I) -> Self {
+        HuffBuilder {
+            nodes: iter.into_iter().collect(),
+            observed: Map::new(),
+        }
+    }
+}
+
+/// Starts a builder with the symbol and weight pairs in a slice, failing
+/// with `BuildError::Empty` on an empty slice, which `try_build` would fail
+/// on later.
+impl<'a, V, W> TryFrom<&'a [(V, W)]> for HuffBuilder<V, W>
+where
+    V: Eq + Clone,
+    W: PartialOrd + Combine + Clone,
+{
+    type Error = BuildError;
+
+    fn try_from(table: &'a [(V, W)]) -> Result<Self, BuildError> {
+        if table.is_empty() {
+            return Err(BuildError::Empty);
+        }
+
+        Ok(HuffBuilder {
+            nodes: table.to_vec(),
+            observed: Map::new(),
+        })
+    }
+}
+
+impl HuffBuilder<u8, usize> {
+    pub fn from_bytes(data: &[u8]) -> Self {
+        HuffBuilder::from_counts(&byte_counts(data))
+    }
+
+    /// Same as `from_bytes`, but counts the bytes across rayon's thread pool.
+    ///
+    /// Each thread counts its own chunk of `data` and the counts are added
+    /// together, so the builder is the same as `from_bytes` would give.
+    #[cfg(feature = "rayon")]
+    pub fn from_bytes_parallel(data: &[u8]) -> Self {
+        use rayon::prelude::*;
+
+        let counts = data
+            .par_chunks(PARALLEL_CHUNK)
+            .map(byte_counts)
+            .reduce(
+                || [0; 256],
+                |mut total, counts| {
+                    for (total, count) in total.iter_mut().zip(counts.iter()) {
+                        *total += *count;
+                    }
+                    total
+                },
+            );
+
+        HuffBuilder::from_counts(&counts)
+    }
+
+    fn from_counts(counts: &[usize; 256]) -> Self {
+        let mut builder = HuffBuilder::new();
+        for (byte, count) in counts.iter().enumerate() {
+            if *count > 0 {
+                builder = builder.add(byte as u8, *count);
+            }
+        }
+
+        builder
+    }
+}
+
+// bytes counted by each thread in `from_bytes_parallel`
+#[cfg(feature = "rayon")]
+const PARALLEL_CHUNK: usize = 64 * 1024;
+
+impl<V: Eq + Clone + MapKey> HuffBuilder<V, usize> {
+    /// Counts how often each symbol occurs in `data`, as `from_bytes` does
+    /// for bytes, for tokens such as `u16` or `u32`.
+    ///
+    /// Symbols are counted as by `observe`, once each in the order they
+    /// first occur.
+    pub fn from_symbols(data: &[V]) -> Self {
+        let mut builder = HuffBuilder::new();
+        for sym in data {
+            builder.observe(sym.clone());
+        }
+
+        builder
+    }
+
+    /// Counts one more occurrence of `sym`, for building from symbols as
+    /// they arrive rather than from a table of counts.
+    ///
+    /// Each symbol is kept once with its running count, so memory grows
+    /// with the number of distinct symbols rather than the length of the
+    /// input. Symbols can also be given with `add`, whose weights are summed
+    /// with the observed counts when building.
+    pub fn observe(&mut self, sym: V) {
+        if let Some(&i) = self.observed.get(&sym) {
+            self.nodes[i].1 += 1;
+            return;
+        }
+
+        self.observed.insert(sym.clone(), self.nodes.len());
+        self.nodes.push((sym, 1));
+    }
+
+    /// Seeds a builder with `counts` after additive smoothing, adding
+    /// `alpha` to the count of every symbol in `alphabet`.
+    ///
+    /// Symbols of the alphabet that were never counted still get a code, so
+    /// data that turns up later, with symbols not seen in the counts, can
+    /// still be coded. Symbols in `counts` but not in `alphabet` are kept and
+    /// smoothed the same way, and a symbol listed in `alphabet` more than
+    /// once is smoothed once. With an `alpha` of zero, unseen symbols get a
+    /// weight of zero, which `try_build` keeps with the longest codes.
+    ///
+    /// Symbols are held as by `observe`, so later observations add to the
+    /// smoothed counts.
+    pub fn from_counts_smoothed(counts: &Map<V, usize>, alphabet: &[V], alpha: usize) -> Self {
+        let mut builder = HuffBuilder::with_capacity(alphabet.len());
+        for sym in alphabet.iter().chain(counts.keys()) {
+            if builder.observed.contains_key(sym) {
+                continue;
+            }
+
+            let count = counts.get(sym).cloned().unwrap_or(0);
+            builder.observed.insert(sym.clone(), builder.nodes.len());
+            builder.nodes.push((sym.clone(), count + alpha));
+        }
+
+        builder
+    }
+}
+
+fn byte_counts(data: &[u8]) -> [usize; 256] {
+    let mut counts = [0usize; 256];
+    for byte in data {
+        counts[*byte as usize] += 1;
+    }
+
+    counts
+}
+
+impl<V: Eq + Clone> HuffBuilder<V, f64> {
+    /// Seeds a builder with symbol probabilities.
+    ///
+    /// Building sums the weights of the two lightest nodes with a single
+    /// `f64` addition at each step, so the merged weights, and with them the
+    /// tree, are the same on every run and platform. The comparisons are
+    /// exact: probabilities that differ only by rounding, such as `0.1 + 0.2`
+    /// and `0.3`, are not treated as ties. True ties are broken as described
+    /// in `try_build`.
+    pub fn from_probabilities(probs: &[(V, f64)]) -> Self {
+        HuffBuilder {
+            nodes: probs.to_vec(),
+            observed: Map::new(),
+        }
+    }
+}
+
+impl<V: Eq + Clone + Ord, W: PartialOrd + Combine + Clone> HuffBuilder<V, W> {
+    /// Builds a tree where no code is longer than `max_len` bits, using the
+    /// package-merge algorithm.
+    ///
+    /// A symbol added more than once becomes a single leaf with its weights
+    /// combined, and symbols of equal weight are ordered by value, as in
+    /// `try_build`, so the tree does not depend on the order symbols were
+    /// added in.
+    ///
+    /// Returns `None` if there are no symbols, or more symbols than can be
+    /// given distinct codes of at most `max_len` bits, or a weight is `NaN`.
+    pub fn build_limited(self, max_len: usize) -> Option<HuffTree<V>> {
+        check_weights(&self.nodes).ok()?;
+
+        // lightest first, the order package-merge takes them in
+        let mut nodes = sorted_symbols(self.nodes, |a, b| Some(a.combine(&b)))?;
+        nodes.reverse();
+
+        let count = nodes.len();
+        let capacity = 1usize.checked_shl(max_len as u32).unwrap_or(usize::MAX);
+        if count == 0 || max_len == 0 || count > capacity {
+            return None;
+        }
+        if count == 1 {
+            return nodes.pop().map(|(v, _)| HuffTree::new_leaf(v));
+        }
+
+        // each item is a weight along with the symbols it covers
+        let leaves: Vec<(W, Vec<usize>)> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.1.clone(), vec![i]))
+            .collect();
+
+        let mut items = leaves.clone();
+
+        for _ in 1..max_len {
+            let mut packages = vec![];
+            let mut pairs = items.into_iter();
+
+            while let (Some((lw, mut ls)), Some((rw, rs))) = (pairs.next(), pairs.next()) {
+                ls.extend(rs);
+                packages.push((lw.combine(&rw), ls));
+            }
+
+            items = Vec::with_capacity(leaves.len() + packages.len());
+            let mut leaves = leaves.iter().cloned().peekable();
+            let mut packages = packages.into_iter().peekable();
+
+            loop {
+                let take_leaf = match (leaves.peek(), packages.peek()) {
+                    (Some(l), Some(p)) => l.0 <= p.0,
+                    (Some(_), None) => true,
+                    (None, Some(_)) => false,
+                    (None, None) => break,
+                };
+
+                if take_leaf {
+                    items.extend(leaves.next());
+                } else {
+                    items.extend(packages.next());
+                }
+            }
+        }
+
+        let mut lengths = vec![0; count];
+        for (_, symbols) in items.into_iter().take(2 * count - 2) {
+            for i in symbols {
+                lengths[i] += 1;
+            }
+        }
+
+        // heaviest first, so ties in length keep the heavier symbol on the left
+        let lengths = nodes
+            .into_iter()
+            .zip(lengths)
+            .rev()
+            .map(|((v, _), len)| (v, len))
+            .collect();
+
+        HuffTree::from_lengths(lengths)
+    }
+}
+
+impl<V: Eq + Clone + MapKey, W: PartialOrd + Combine> HuffBuilder<V, W> {
+    /// Adds every symbol and weight pair in `table`, making room for as
+    /// many as its size hint promises up front.
+    pub fn add_table<I>(mut self, table: I) -> Self
+    where
+        I: IntoIterator<Item = (V, W)>,
+    {
+        let table = table.into_iter();
+        self.nodes.reserve(table.size_hint().0);
+        self.nodes.extend(table);
+        self
+    }
+
+    /// Adds every symbol of `other`, such as counts from another shard of
+    /// the data, with the weights of symbols in both summed when building.
+    pub fn merge(mut self, other: HuffBuilder<V, W>) -> Self {
+        let offset = self.nodes.len();
+        for (sym, i) in other.observed {
+            self.observed.entry(sym).or_insert(offset + i);
+        }
+        self.nodes.extend(other.nodes);
+
+        self
+    }
+}
+
+#[cfg(feature = "std")]
+fn unknown_symbol<V: Debug>(value: &V) -> Error {
+    HuffError::UnknownSymbol(format!("{:?}", value)).into()
+}
+
+#[cfg(feature = "std")]
+fn sum_code_lengths<V, F>(data: &[V], len: F) -> std::io::Result<usize>
+where
+    V: Debug,
+    F: Fn(&V) -> Option<usize>,
+{
+    let mut bit_len = 0;
+    for value in data {
+        match len(value) {
+            Some(len) => bit_len += len,
+            None => return Err(unknown_symbol(value)),
+        }
+    }
+
+    Ok(bit_len)
+}
+
+/// Builds a tree straight from a frequency table.
+///
+/// An empty table fails with `BuildError::Empty`, while a table with a single
+/// entry builds a lone leaf with a one bit code. Ties are broken as in
+/// `HuffBuilder::try_build`.
+pub fn huff_tree_from_table<V, W>(table: Map<V, W>) -> Result<HuffTree<V>, BuildError>
+where
+    V: Eq + Clone + MapKey + Ord,
+    W: PartialOrd + Combine,
+{
+    HuffBuilder::new().add_table(table).try_build()
+}
+
+/// Builds a tree from a slice of symbol and weight pairs, as
+/// `huff_tree_from_table` does.
+pub fn huff_tree_from_slice<V, W>(table: &[(V, W)]) -> Result<HuffTree<V>, BuildError>
+where
+    V: Eq + Clone + MapKey + Ord,
+    W: PartialOrd + Combine + Clone,
+{
+    HuffBuilder::new().add_table(table.iter().cloned()).try_build()
+}
+
+// A code split into runs of up to `BitWriter::MAX_BITS` bits, so that
+// writing it takes one `write_bits` call per run rather than one call per bit.
+#[cfg(feature = "std")]
+struct PackedCode {
+    len: usize,
+    runs: Vec<(u64, u32)>,
+}
+
+#[cfg(feature = "std")]
+impl PackedCode {
+    fn new(code: &[bool]) -> Self {
+        let runs = code
+            .chunks(BitWriter::<Vec<u8>>::MAX_BITS as usize)
+            .map(|run| {
+                let bits = run.iter().fold(0, |bits, bit| bits << 1 | *bit as u64);
+                (bits, run.len() as u32)
+            })
+            .collect();
+
+        PackedCode {
+            len: code.len(),
+            runs,
+        }
+    }
+
+    fn to_bits(&self) -> Vec<bool> {
+        self.runs
+            .iter()
+            .flat_map(|&(bits, len)| (0..len).rev().map(move |i| bits >> i & 1 == 1))
+            .collect()
+    }
+}
+
+// The codes of a `HuffWriter`, either in a map or, for symbols that convert
+// to small integers, in a `Vec` indexed by them.
+#[cfg(feature = "std")]
+enum Codes<V> {
+    Map(Map<V, PackedCode>),
+    Indexed(Vec<Option<PackedCode>>, fn(&V) -> usize),
+}
+
+#[cfg(feature = "std")]
+impl<V: MapKey> Codes<V> {
+    fn new(encoding: Map<V, Vec<bool>>) -> Self {
+        let codes = encoding
+            .into_iter()
+            .map(|(v, code)| (v, PackedCode::new(&code)))
+            .collect();
+
+        Codes::Map(codes)
+    }
+
+    fn indexed(encoding: Map<V, Vec<bool>>) -> Self
+    where
+        V: Copy + Into<usize>,
+    {
+        fn index<V: Copy + Into<usize>>(value: &V) -> usize {
+            (*value).into()
+        }
+
+        let len = encoding.keys().map(|v| index(v) + 1).max().unwrap_or(0);
+        let mut codes: Vec<Option<PackedCode>> = (0..len).map(|_| None).collect();
+        for (v, code) in encoding {
+            codes[index(&v)] = Some(PackedCode::new(&code));
+        }
+
+        Codes::Indexed(codes, index::<V>)
+    }
+
+    fn get(&self, value: &V) -> Option<&PackedCode> {
+        match *self {
+            Codes::Map(ref codes) => codes.get(value),
+            Codes::Indexed(ref codes, index) => codes.get(index(value)).and_then(Option::as_ref),
+        }
+    }
+}
+
+/// What a `HuffWriter` made `with_stats` has written so far.
+#[cfg(feature = "std")]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct WriteStats<V: MapKey> {
+    /// How many times each symbol was written.
+    pub counts: Map<V, u64>,
+    /// Number of symbols written.
+    pub symbols: u64,
+    /// Number of bits the symbols were written as, without any padding.
+    pub total_bits: u64,
+}
+
+#[cfg(feature = "std")]
+impl<V: MapKey + Clone> WriteStats<V> {
+    fn new() -> Self {
+        WriteStats {
+            counts: Map::new(),
+            symbols: 0,
+            total_bits: 0,
+        }
+    }
+
+    fn record(&mut self, value: &V, len: usize) {
+        match self.counts.get_mut(value) {
+            Some(count) => *count += 1,
+            None => {
+                self.counts.insert(value.clone(), 1);
+            }
+        }
+        self.symbols += 1;
+        self.total_bits += len as u64;
+    }
+}
+
+#[cfg(feature = "std")]
+pub struct HuffWriter<V: Eq + Clone + MapKey, W: Write> {
+    codes: Codes<V>,
+    writer: BitWriter<W>,
+    eof: Option<V>,
+    stats: Option<WriteStats<V>>,
+}
+
+/// A `HuffWriter` over a boxed writer, for holding one whose sink is picked
+/// at run time without carrying the writer type around.
+#[cfg(feature = "std")]
+pub type BoxedHuffWriter<V> = HuffWriter<V, Box<dyn Write>>;
+
+#[cfg(feature = "std")]
+impl<V: Eq + Clone + MapKey, W: Write> HuffWriter<V, W> {
+    pub fn new(tree: HuffTree<V>, writer: W) -> Self {
+        HuffWriter::with_order(tree, writer, BitOrder::MsbFirst)
+    }
+
+    /// Creates a writer that packs bits into each byte in the given order.
+    pub fn with_order(tree: HuffTree<V>, writer: W, order: BitOrder) -> Self {
+        HuffWriter {
+            codes: Codes::new(tree.encoding()),
+            writer: BitWriter::with_order(writer, order),
+            eof: None,
+            stats: None,
+        }
+    }
+
+    /// Creates a writer as `new` does, failing with an `InvalidInput` error
+    /// wrapping `HuffError::CodeTooLong` if any code in the tree is longer
+    /// than `max_len` bits.
+    ///
+    /// This catches a tree that does not suit the format being written
+    /// before anything is written, see `HuffBuilder::build_limited` for
+    /// building one that does.
+    pub fn new_limited(tree: HuffTree<V>, writer: W, max_len: usize) -> std::io::Result<Self> {
+        if tree.max_code_length() > max_len {
+            return Err(HuffError::CodeTooLong(max_len).into());
+        }
+
+        Ok(HuffWriter::new(tree, writer))
+    }
+
+    /// Creates a writer that looks codes up in a `Vec` indexed by symbol,
+    /// rather than hashing each symbol.
+    ///
+    /// Meant for small integer symbols such as `u8` and `u16`: the `Vec`
+    /// has an entry for every value up to the largest symbol in the tree.
+    pub fn new_indexed(tree: HuffTree<V>, writer: W) -> Self
+    where
+        V: Copy + Into<usize>,
+    {
+        HuffWriter {
+            codes: Codes::indexed(tree.encoding()),
+            writer: BitWriter::new(writer),
+            eof: None,
+            stats: None,
+        }
+    }
+
+    /// Creates a writer that carries on from the bits already written to
+    /// `writer`, so coded symbols can follow raw bits without padding to a
+    /// byte in between.
+    pub fn from_bit_writer(tree: HuffTree<V>, writer: BitWriter<W>) -> Self {
+        HuffWriter {
+            codes: Codes::new(tree.encoding()),
+            writer,
+            eof: None,
+            stats: None,
+        }
+    }
+
+    /// Creates a writer from a ready made code table, such as one from
+    /// `canonical_encoding`, without needing the tree.
+    ///
+    /// The table is used as given, it is up to the caller that it is a
+    /// prefix code the reading side can decode.
+    pub fn from_table(table: Map<V, Vec<bool>>, writer: W) -> Self {
+        HuffWriter {
+            codes: Codes::new(table),
+            writer: BitWriter::new(writer),
+            eof: None,
+            stats: None,
+        }
+    }
+
+    /// Sets the end of stream symbol written by `write_eof`.
+    pub fn with_eof(mut self, eof: V) -> Self {
+        self.eof = Some(eof);
+        self
+    }
+
+    /// Counts each symbol written from here on, for `stats`.
+    ///
+    /// Writers do not count unless asked to, so there is no cost otherwise.
+    pub fn with_stats(mut self) -> Self {
+        self.stats = Some(WriteStats::new());
+        self
+    }
+
+    /// What has been written since `with_stats`, or `None` if the writer
+    /// was not asked to count.
+    ///
+    /// Comparing the counts against the weights the tree was built from
+    /// shows whether the tree still suits the data being written.
+    pub fn stats(&self) -> Option<&WriteStats<V>> {
+        self.stats.as_ref()
+    }
+
+    /// Writes the code for `value`.
+    ///
+    /// A symbol that is not in the tree fails with an `InvalidInput` error
+    /// wrapping `HuffError::UnknownSymbol`.
+    pub fn write(&mut self, value: &V) -> std::io::Result<()>
+    where
+        V: Debug,
+    {
+        let code = match self.codes.get(value) {
+            Some(code) => code,
+            None => return Err(unknown_symbol(value)),
+        };
+
+        for &(bits, len) in &code.runs {
+            self.writer.write_bits(bits, len)?;
+        }
+        if let Some(ref mut stats) = self.stats {
+            stats.record(value, code.len);
+        }
+
+        Ok(())
+    }
+
+    /// Writes every symbol in `values`.
+    ///
+    /// Stops at the first symbol that is not in the tree, failing with an
+    /// `InvalidInput` error wrapping `HuffError::UnknownSymbolAt` with its
+    /// index. The symbols before it have already been written.
+    pub fn write_all(&mut self, values: &[V]) -> std::io::Result<()> {
+        for (i, value) in values.iter().enumerate() {
+            let code = match self.codes.get(value) {
+                Some(code) => code,
+                None => return Err(HuffError::UnknownSymbolAt(i).into()),
+            };
+
+            for &(bits, len) in &code.runs {
+                self.writer.write_bits(bits, len)?;
+            }
+            if let Some(ref mut stats) = self.stats {
+                stats.record(value, code.len);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes every symbol from `iter`, returning how many were written.
+    ///
+    /// Stops at the first symbol that is not in the tree, as `write` does.
+    pub fn write_iter<I: IntoIterator<Item = V>>(&mut self, iter: I) -> std::io::Result<usize>
+    where
+        V: Debug,
+    {
+        let mut count = 0;
+        for value in iter {
+            self.write(&value)?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Writes the end of stream symbol set by `with_eof`.
+    ///
+    /// Fails with `InvalidInput` if no end of stream symbol was set or it is
+    /// not in the tree.
+    pub fn write_eof(&mut self) -> std::io::Result<()>
+    where
+        V: Debug,
+    {
+        let eof = match self.eof {
+            Some(ref eof) => eof.clone(),
+            None => {
+                return Err(HuffError::NoEofSymbol.into());
+            }
+        };
+
+        self.write(&eof)
+    }
+
+    /// Number of bits `data` would be written as, without any padding.
+    pub fn encoded_bit_len(&self, data: &[V]) -> std::io::Result<usize>
+    where
+        V: Debug,
+    {
+        sum_code_lengths(data, |value| self.codes.get(value).map(|code| code.len))
+    }
+
+    /// The code `write` would write for `value`, or `None` if it is not in
+    /// the tree.
+    ///
+    /// Codes are kept packed for writing, so this unpacks a copy, meant for
+    /// laying a code out by hand with a `BitWriter` alongside other fields.
+    pub fn code_for(&self, value: &V) -> Option<Vec<bool>> {
+        self.codes.get(value).map(PackedCode::to_bits)
+    }
+
+    /// Number of bits written so far, as `BitWriter::bits_written` counts
+    /// them.
+    pub fn bits_written(&self) -> u64 {
+        self.writer.bits_written()
+    }
+
+    /// How many bits into the current byte the next code starts, from 0 to
+    /// 7, as `BitWriter::bit_position` counts them.
+    pub fn bit_position(&self) -> usize {
+        self.writer.bit_position()
+    }
+
+    /// Pads the current byte with `0` bits, so whatever is written next
+    /// starts on a byte boundary, returning how many bits were added.
+    ///
+    /// Segments written this way can be read back one after another with
+    /// `HuffReader::skip_to_byte` between them.
+    pub fn align_to_byte(&mut self) -> std::io::Result<usize> {
+        self.writer.align_to_byte()
+    }
+
+    /// Hands back the bit writer without padding, so raw bits can follow
+    /// the last code directly.
+    pub fn into_bit_writer(self) -> BitWriter<W> {
+        self.writer
+    }
+
+    /// Finishes the current output as `finish` does and carries on writing
+    /// to `writer`, handing back the finished writer.
+    ///
+    /// The codes, bit order, end of stream symbol and any stats are kept, so
+    /// one writer can code many small frames without rebuilding its codes.
+    /// On an error finishing the old writer, the new one is in place all the
+    /// same.
+    pub fn reset(&mut self, writer: W) -> std::io::Result<W> {
+        let order = self.writer.order();
+        mem::replace(&mut self.writer, BitWriter::with_order(writer, order)).finish()
+    }
+
+    /// Writes out any buffered bits and hands back the inner writer.
+    ///
+    /// The final byte is padded with `0` bits when the written codes do not
+    /// end on a byte boundary. Dropping the writer also writes out the
+    /// buffered bits, but only `finish` reports an error doing so.
+    pub fn finish(self) -> std::io::Result<W> {
+        self.writer.finish()
+    }
+}
+
+// Where a `HuffReader` finds symbols: by walking down a tree a bit at a time,
+// or with a single lookup in a decode table. A tree is kept with the length
+// of its longest code, the most bits a walk may take.
+#[cfg(feature = "std")]
+enum Decoder<V: Eq + Clone> {
+    Tree(Box<HuffTree<V>>, usize),
+    Table(DecodeTable<V>),
+}
+
+// a reader may be handed a tree from untrusted input, so it is dropped as
+// `HuffTree::drop_deep` drops it
+#[cfg(feature = "std")]
+impl<V: Eq + Clone> Drop for Decoder<V> {
+    fn drop(&mut self) {
+        if let Decoder::Tree(ref mut tree, _) = *self {
+            tree.balance_for_drop();
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<V: Eq + Clone> Decoder<V> {
+    fn tree(tree: HuffTree<V>) -> Self {
+        let max_len = tree.max_code_length();
+        Decoder::Tree(Box::new(tree), max_len)
+    }
+
+    // Decodes the next symbol as `HuffTree::decode_with` does. With `keep`
+    // set, every bit read is put back into `reader` afterwards.
+    fn decode<R: Read>(&self, reader: &mut BitReader<R>, keep: bool) -> std::io::Result<Option<V>> {
+        match *self {
+            Decoder::Tree(ref tree, max_len) if keep => {
+                let mut path = vec![];
+                let result = tree.decode_with(|| {
+                    let bit = next_bit_within(reader, path.len(), max_len)?;
+                    path.extend(bit);
+                    Ok(bit)
+                });
+
+                reader.unread(&path);
+                result
+            }
+            Decoder::Tree(ref tree, max_len) => {
+                let mut taken = 0;
+                tree.decode_with(|| {
+                    let bit = next_bit_within(reader, taken, max_len)?;
+                    taken += 1;
+                    Ok(bit)
+                })
+            }
+            Decoder::Table(ref table) => decode_from_table(table, reader, keep),
+        }
+    }
+
+    // Decodes the next symbol along with the bits of its code.
+    fn decode_traced<R: Read>(
+        &self,
+        reader: &mut BitReader<R>,
+    ) -> std::io::Result<Option<(V, Vec<bool>)>> {
+        let mut path = vec![];
+        let result = match *self {
+            Decoder::Tree(ref tree, max_len) => tree.decode_with(|| {
+                let bit = next_bit_within(reader, path.len(), max_len)?;
+                path.extend(bit);
+                Ok(bit)
+            }),
+            Decoder::Table(ref table) => {
+                // a lookup takes no more bits than a table index, so the
+                // code is the start of those
+                let mut ahead = Ok(());
+                while path.len() < table.bits() {
+                    match reader.read_bit() {
+                        Ok(Some(bit)) => path.push(bit),
+                        Ok(None) => break,
+                        Err(e) => {
+                            ahead = Err(e);
+                            break;
+                        }
+                    }
+                }
+                reader.unread(&path);
+                ahead?;
+
+                let before = reader.bits_read();
+                let result = decode_from_table(table, reader, false);
+                path.truncate((reader.bits_read() - before) as usize);
+                result
+            }
+        };
+
+        Ok(result?.map(|value| (value, path)))
+    }
+}
+
+// Reads the next bit of a code, failing rather than reading past `max_len`
+// bits once `taken` have been read. Only a tree that does not match its
+// cached length could walk that far, but this keeps a single symbol from
+// ever taking more than the longest code.
+#[cfg(feature = "std")]
+fn next_bit_within<R: Read>(
+    reader: &mut BitReader<R>,
+    taken: usize,
+    max_len: usize,
+) -> std::io::Result<Option<bool>> {
+    if taken >= max_len {
+        return Err(HuffError::InvalidCode.into());
+    }
+
+    reader.read_bit()
+}
+
+// Reads a table index worth of bits, or what is left of the input if that is
+// less, and puts back the bits past the code found. Input that would block
+// counts as short too, so a code that is already there can still be found.
+#[cfg(feature = "std")]
+fn decode_from_table<V: Clone, R: Read>(
+    table: &DecodeTable<V>,
+    reader: &mut BitReader<R>,
+    keep: bool,
+) -> std::io::Result<Option<V>> {
+    let mut index = 0;
+    let mut read = 0;
+    let mut blocked = None;
+    while read < table.bits() {
+        match reader.read_bit() {
+            Ok(Some(bit)) => {
+                index = index << 1 | bit as usize;
+                read += 1;
+            }
+            Ok(None) => break,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                blocked = Some(e);
+                break;
+            }
+            Err(e) => {
+                reader.unread_bits(index as u64, read);
+                return Err(e);
+            }
+        }
+    }
+
+    if read == 0 {
+        return match blocked {
+            Some(e) => Err(e),
+            None => Ok(None),
+        };
+    }
+
+    // the rest of the code may yet arrive, so the bits are kept for a retry
+    if let Some(e) = blocked {
+        match table.lookup(index << (table.bits() - read)) {
+            Some((_, len)) if len <= read => {}
+            _ => {
+                reader.unread_bits(index as u64, read);
+                return Err(e);
+            }
+        }
+    }
+
+    // no code is a prefix of another, so padding a short read out with zeros
+    // still finds the one code it can start
+    let found = match table.lookup(index << (table.bits() - read)) {
+        Some((value, len)) if len <= read => Ok((value.clone(), len)),
+        Some(_) => Err(HuffError::UnexpectedEof),
+        None => Err(HuffError::InvalidCode),
+    };
+
+    match found {
+        Ok((value, len)) => {
+            let unused = if keep { read } else { read - len };
+            reader.unread_bits(index as u64, unused);
+            Ok(Some(value))
+        }
+        Err(err) => {
+            if keep {
+                reader.unread_bits(index as u64, read);
+            }
+            Err(err.into())
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub struct HuffReader<V: Eq + Clone, R: Read> {
+    decoder: Decoder<V>,
+    reader: BitReader<R>,
+    eof: Option<V>,
+}
+
+/// A `HuffReader` over a boxed reader, the reading side of
+/// `BoxedHuffWriter`.
+#[cfg(feature = "std")]
+pub type BoxedHuffReader<V> = HuffReader<V, Box<dyn Read>>;
+
+#[cfg(feature = "std")]
+impl<V: Eq + Clone, R: Read> HuffReader<V, R> {
+    pub fn new(tree: HuffTree<V>, reader: R) -> Self {
+        HuffReader::with_order(tree, reader, BitOrder::MsbFirst)
+    }
+
+    /// Creates a reader that unpacks bits from each byte in the given order.
+    pub fn with_order(tree: HuffTree<V>, reader: R, order: BitOrder) -> Self {
+        HuffReader {
+            decoder: Decoder::tree(tree),
+            reader: BitReader::with_order(reader, order),
+            eof: None,
+        }
+    }
+
+    /// Creates a reader that decodes each symbol with a lookup in `table`,
+    /// rather than walking down a tree a bit at a time.
+    ///
+    /// Near the end of the input, where fewer bits are left than the table
+    /// is indexed by, the bits that are left are looked up on their own.
+    /// The reader otherwise behaves as one made with `new` from the tree the
+    /// table was built from.
+    pub fn from_decode_table(table: DecodeTable<V>, reader: R) -> Self {
+        HuffReader {
+            decoder: Decoder::Table(table),
+            reader: BitReader::new(reader),
+            eof: None,
+        }
+    }
+
+    /// Sets the end of stream symbol `read_until_eof` stops at.
+    pub fn with_eof(mut self, eof: V) -> Self {
+        self.eof = Some(eof);
+        self
+    }
+
+    /// Decodes the next symbol.
+    ///
+    /// Every node in a `HuffTree` has two children, so any run of bits
+    /// reaches a leaf after at most `max_code_length` bits and a corrupt
+    /// stream cannot walk off the tree. The longest code is worked out when
+    /// the reader is made, and no single read takes more bits than it. The
+    /// only bit pattern that can be rejected is a `1` for a lone leaf tree,
+    /// which fails with `InvalidData`. Otherwise corruption decodes to wrong
+    /// symbols, so framed data should carry its own check if it needs one.
+    pub fn read(&mut self) -> std::io::Result<V> {
+        match self.decoder.decode(&mut self.reader, false)? {
+            Some(value) => Ok(value),
+            None => Err(HuffError::UnexpectedEof.into()),
+        }
+    }
+
+    /// Decodes the next symbol as `read` does, but gives `default` in place
+    /// of bits that are not a code of the tree, skipping to the next byte
+    /// boundary to try to pick up again from there.
+    ///
+    /// This is best effort. As `read` explains, every node having two
+    /// children means nearly any bits decode to some symbol, so most
+    /// corruption still gives wrong symbols rather than `default`, and the
+    /// next byte boundary need not start a code either. Errors other than
+    /// an invalid code, such as the input ending, are returned as they are.
+    pub fn read_or(&mut self, default: V) -> std::io::Result<V> {
+        match self.read() {
+            Err(ref e) if e.kind() == ErrorKind::InvalidData => {
+                self.reader.skip_to_byte()?;
+                Ok(default)
+            }
+            result => result,
+        }
+    }
+
+    /// Decodes the next symbol as `read` does, along with the bits of the
+    /// code it was read from.
+    ///
+    /// Meant for tracking down where a corrupt stream goes astray, by
+    /// checking the path taken against the codes the writer used.
+    pub fn read_traced(&mut self) -> std::io::Result<(V, Vec<bool>)> {
+        match self.decoder.decode_traced(&mut self.reader)? {
+            Some(traced) => Ok(traced),
+            None => Err(HuffError::UnexpectedEof.into()),
+        }
+    }
+
+    /// Decodes the next symbol if all of its bits can be had without
+    /// waiting, for decoding from a non-blocking reader.
+    ///
+    /// Returns `None` when the inner reader fails with `WouldBlock` before
+    /// the symbol is complete. The bits read towards it are kept, so calling
+    /// again once more input is ready carries on where this left off. Other
+    /// errors, including `UnexpectedEof` at the end of the input, are as for
+    /// `read`.
+    pub fn try_read(&mut self) -> std::io::Result<Option<V>> {
+        // peeking puts every bit back even when blocked, and leaves the
+        // symbol's bits held so reading it does not touch the inner reader
+        match self.decoder.decode(&mut self.reader, true) {
+            Ok(Some(_)) => self.read().map(Some),
+            Ok(None) => Err(HuffError::UnexpectedEof.into()),
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Decodes the next symbol without consuming it, `None` if the input has
+    /// ended on a symbol boundary.
+    ///
+    /// The bits read to decode the symbol are held in the reader and read
+    /// again by the next call that decodes, so a following `read` returns the
+    /// same symbol. `bits_read` does not count the held bits.
+    pub fn peek(&mut self) -> std::io::Result<Option<V>> {
+        self.decoder.decode(&mut self.reader, true)
+    }
+
+    /// Whether the input has ended apart from the padding that finishes the
+    /// last byte.
+    ///
+    /// True when fewer than eight bits are left and all of them are `0`, as
+    /// `HuffWriter::finish` pads. No bits are consumed, so reading can carry
+    /// on either way.
+    ///
+    /// Padding is made of whole codes as often as not, the leftmost leaf's
+    /// code being all `0` bits, so this can only tell that nothing but
+    /// padding is left once the caller has read every symbol it expects.
+    pub fn at_clean_end(&mut self) -> std::io::Result<bool> {
+        let mut rest = vec![];
+        while rest.len() < 8 {
+            match self.reader.read_bit()? {
+                Some(bit) => rest.push(bit),
+                None => break,
+            }
+        }
+
+        self.reader.unread(&rest);
+
+        Ok(rest.len() < 8 && !rest.contains(&true))
+    }
+
+    /// Skips the padding after a segment written with
+    /// `HuffWriter::align_to_byte`, returning how many bits were skipped.
+    ///
+    /// The bits skipped are not checked, and fewer are skipped if the input
+    /// ends first.
+    pub fn skip_to_byte(&mut self) -> std::io::Result<usize> {
+        self.reader.skip_to_byte()
+    }
+
+    /// Number of bits consumed from the input so far.
+    pub fn bits_read(&self) -> u64 {
+        self.reader.bits_read()
+    }
+
+    /// Carries on reading from `reader`, handing back the old inner reader as
+    /// `into_inner` does.
+    ///
+    /// The tree or decode table, bit order and end of stream symbol are
+    /// kept, so one reader can decode many small frames without rebuilding
+    /// them. Bits left over from the old reader, including any held by
+    /// `peek`, are dropped and `bits_read` starts again from zero.
+    pub fn reset(&mut self, reader: R) -> R {
+        let order = self.reader.order();
+        mem::replace(&mut self.reader, BitReader::with_order(reader, order)).into_inner()
+    }
+
+    /// Hands back the inner reader.
+    ///
+    /// Bytes are only read from the inner reader as their bits are needed,
+    /// so it is left just past the byte holding the last bit consumed. Any
+    /// bits left in that byte are dropped.
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+
+    /// Decodes symbols into `out` until it is full or the input ends,
+    /// returning how many were decoded.
+    ///
+    /// Input that ends part way through a symbol is treated as the end of the
+    /// input, and the bits of that partial symbol are dropped.
+    pub fn read_into(&mut self, out: &mut [V]) -> std::io::Result<usize> {
+        for (count, slot) in out.iter_mut().enumerate() {
+            match self.decoder.decode(&mut self.reader, false) {
+                Ok(Some(value)) => *slot = value,
+                Ok(None) => return Ok(count),
+                Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(count),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(out.len())
+    }
+
+    /// Decodes up to `n` symbols onto the end of `out`, returning how many
+    /// were decoded.
+    ///
+    /// Fewer than `n` are decoded if the input ends on a symbol boundary
+    /// first. Input that ends part way through a symbol fails with
+    /// `UnexpectedEof`, leaving the symbols before it in `out`.
+    pub fn read_n(&mut self, n: usize, out: &mut Vec<V>) -> std::io::Result<usize> {
+        for count in 0..n {
+            match self.decoder.decode(&mut self.reader, false)? {
+                Some(value) => out.push(value),
+                None => return Ok(count),
+            }
+        }
+
+        Ok(n)
+    }
+
+    /// Decodes exactly `n` symbols, the strict counterpart of `read_n`.
+    ///
+    /// Input that ends before the `n`th symbol fails with `UnexpectedEof`,
+    /// even if it ends cleanly on a symbol boundary. As with
+    /// `Read::read_exact`, the symbols decoded before the failure are
+    /// consumed and lost.
+    pub fn read_exact(&mut self, n: usize) -> std::io::Result<Vec<V>> {
+        // `n` may come from untrusted input, so grow only as symbols arrive
+        let mut values = vec![];
+        for _ in 0..n {
+            values.push(self.read()?);
+        }
+
+        Ok(values)
+    }
+
+    /// Decodes symbols until the input ends on a symbol boundary, failing
+    /// once there are more than `max_symbols` of them.
+    ///
+    /// Meant for untrusted input, where a short stream can otherwise decode
+    /// to a huge number of symbols. Too many symbols fail with an
+    /// `InvalidData` error wrapping `HuffError::LimitExceeded`, and input
+    /// that ends part way through a symbol fails with `UnexpectedEof`. As
+    /// with the `Iterator` impl, the padding in the last byte may decode to
+    /// extra symbols.
+    pub fn read_all_bounded(&mut self, max_symbols: usize) -> std::io::Result<Vec<V>> {
+        let mut values = vec![];
+        while let Some(value) = self.decoder.decode(&mut self.reader, false)? {
+            if values.len() == max_symbols {
+                return Err(HuffError::LimitExceeded(max_symbols).into());
+            }
+            values.push(value);
+        }
+
+        Ok(values)
+    }
+
+    /// Decodes symbols up to the end of stream symbol set by `with_eof`.
+    ///
+    /// The end of stream symbol is consumed but not returned, so any padding
+    /// after it is never decoded. Input that ends before the end of stream
+    /// symbol fails with `UnexpectedEof`, and a reader with no end of stream
+    /// symbol set fails with `InvalidInput`.
+    pub fn read_until_eof(&mut self) -> std::io::Result<Vec<V>> {
+        let eof = match self.eof {
+            Some(ref eof) => eof.clone(),
+            None => {
+                return Err(HuffError::NoEofSymbol.into());
+            }
+        };
+
+        let mut values = vec![];
+        loop {
+            let value = self.read()?;
+            if value == eof {
+                return Ok(values);
+            }
+            values.push(value);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<V: Eq + Clone + Into<u8> + From<u8>, R: Read> HuffReader<V, R> {
+    /// Reads a tree written by `HuffTree::serialize` from the front of
+    /// `reader`, then decodes symbols from the rest of it.
+    pub fn from_stream(mut reader: R) -> std::io::Result<Self> {
+        let tree = HuffTree::deserialize(&mut reader)?;
+        Ok(HuffReader::new(tree, reader))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, V: Eq + Clone> HuffReader<V, &'a [u8]> {
+    /// Creates a reader over bytes in memory, borrowing them rather than
+    /// needing them wrapped in a `Cursor`.
+    pub fn from_slice(tree: HuffTree<V>, data: &'a [u8]) -> Self {
+        HuffReader::new(tree, data)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> HuffReader<char, R> {
+    /// Decodes up to `count` characters straight into a `String`.
+    ///
+    /// Input that ends on a symbol boundary before `count` characters gives
+    /// the ones decoded so far, while input that ends part way through a
+    /// symbol fails with `UnexpectedEof`.
+    pub fn read_string(&mut self, count: usize) -> std::io::Result<String> {
+        let mut string = String::with_capacity(count);
+        for _ in 0..count {
+            match self.decoder.decode(&mut self.reader, false)? {
+                Some(c) => string.push(c),
+                None => break,
+            }
+        }
+
+        Ok(string)
+    }
+}
+
+/// Yields decoded symbols until the input ends on a symbol boundary.
+///
+/// Input that ends part way through a symbol yields an `UnexpectedEof` error.
+#[cfg(feature = "std")]
+impl<V: Eq + Clone, R: Read> Iterator for HuffReader<V, R> {
+    type Item = std::io::Result<V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.decoder.decode(&mut self.reader, false) {
+            Ok(Some(value)) => Some(Ok(value)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    #[test]
+    fn build_simple_tree() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let expected = HuffTree::new_node(
+            HuffTree::new_leaf('d'),
+            HuffTree::new_node(HuffTree::new_leaf('b'), HuffTree::new_leaf('a')),
+        );
+
+        assert_eq!(expected, tree);
+    }
+
+    #[test]
+    fn build_flat_tree() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 1)
+            .add('c', 1)
+            .add('d', 1)
+            .build()
+            .unwrap();
+
+        let expected = HuffTree::new_node(
+            HuffTree::new_node(HuffTree::new_leaf('a'), HuffTree::new_leaf('b')),
+            HuffTree::new_node(HuffTree::new_leaf('c'), HuffTree::new_leaf('d')),
+        );
+
+        assert_eq!(expected, tree);
+    }
+
+    #[test]
+    fn build_tree_from_table() {
+        let mut table = HashMap::new();
+        {
+            table.insert('a', 2);
+            table.insert('b', 1);
+        }
+
+        let tree = HuffBuilder::new().add_table(table).build().unwrap();
+
+        let expected = HuffTree::new_node(HuffTree::new_leaf('a'), HuffTree::new_leaf('b'));
+
+        assert_eq!(expected, tree);
+    }
+
+    #[test]
+    fn build_with_custom_combine() {
+        #[derive(Debug, PartialEq, PartialOrd)]
+        struct Saturating(u8);
+
+        impl Combine for Saturating {
+            fn combine(&self, other: &Self) -> Self {
+                Saturating(self.0.saturating_add(other.0))
+            }
+        }
+
+        // merging `d` with `a` and `b` saturates at 255, where `build_checked`
+        // over `u8` would fail
+        let tree = HuffBuilder::new()
+            .add('a', Saturating(50))
+            .add('b', Saturating(100))
+            .add('c', Saturating(200))
+            .add('d', Saturating(200))
+            .build()
+            .unwrap();
+
+        assert_eq!(Some(vec![('d', 2), ('b', 3), ('a', 3), ('c', 1)]), tree.code_lengths());
+    }
+
+    #[test]
+    fn build_with_escape() {
+        let builder = HuffBuilder::<char, u32>::new()
+            .add('e', 12)
+            .add('t', 9)
+            .add('a', 8)
+            .add('q', 1)
+            .add('z', 1)
+            .add('x', 2);
+
+        let expected = HuffBuilder::<char, u32>::new()
+            .add('e', 12)
+            .add('t', 9)
+            .add('a', 8)
+            .add('*', 4)
+            .build()
+            .unwrap();
+
+        assert_eq!(expected, builder.with_escape(3, '*').build().unwrap());
+
+        let builder = HuffBuilder::<char, u32>::new().add('a', 1).add('b', 2);
+        assert_eq!(vec!['b', 'a'], builder.with_escape(2, '*').build().unwrap().symbols());
+    }
+
+    #[test]
+    fn build_presorted_tree() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('d', 10)
+            .add('b', 2)
+            .add('a', 1)
+            .build_presorted()
+            .unwrap();
+
+        let expected = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        assert_eq!(expected, tree);
+        assert_eq!(Err(BuildError::Empty), HuffBuilder::<char, u32>::new().build_presorted());
+    }
+
+    #[test]
+    #[should_panic(expected = "descending weight")]
+    #[cfg(debug_assertions)]
+    fn build_presorted_checks_order() {
+        let _ = HuffBuilder::<char, u32>::new().add('a', 1).add('d', 10).build_presorted();
+    }
+
+    #[test]
+    fn build_sums_duplicate_symbols() {
+        let expected = HuffBuilder::<char, u32>::new()
+            .add('a', 8)
+            .add('b', 4)
+            .add('c', 1)
+            .build()
+            .unwrap();
+
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 3)
+            .add('b', 4)
+            .add('a', 5)
+            .add('c', 1)
+            .build()
+            .unwrap();
+        assert_eq!(expected, tree);
+
+        let tree = HuffBuilder::new()
+            .add_table(vec![('c', 1), ('a', 5), ('b', 4)])
+            .add_table(vec![('a', 3)])
+            .build()
+            .unwrap();
+        assert_eq!(expected, tree);
+        assert_eq!(3, tree.encoding().len());
+    }
+
+    #[test]
+    fn build_tree_from_bytes() {
+        let tree = HuffBuilder::from_bytes(b"abacab").build().unwrap();
+
+        let expected = HuffBuilder::<u8, usize>::new()
+            .add(b'a', 3)
+            .add(b'b', 2)
+            .add(b'c', 1)
+            .build()
+            .unwrap();
+
+        assert_eq!(expected, tree);
+        assert_eq!(3, tree.encoding().len());
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn parallel_counts_match_serial() {
+        let data: Vec<u8> = (0..500_000u64).map(|i| (i * i % 251) as u8).collect();
+
+        let serial = HuffBuilder::from_bytes(&data);
+        let parallel = HuffBuilder::from_bytes_parallel(&data);
+
+        assert_eq!(serial.nodes, parallel.nodes);
+    }
+
+    #[test]
+    fn encoding_map() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 1)
+            .add('c', 1)
+            .add('d', 1)
+            .build()
+            .unwrap();
+
+        let mut expected = HashMap::new();
+        expected.insert('a', vec![false, false]);
+        expected.insert('b', vec![false, true]);
+        expected.insert('c', vec![true, false]);
+        expected.insert('d', vec![true, true]);
+
+        assert_eq!(expected, tree.encoding());
+    }
+
+    #[test]
+    fn encoding_ref_keeps_tree() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let encoding = tree.encoding_ref();
+
+        assert_eq!(tree.clone().encoding(), encoding);
+        assert_eq!(vec!['d', 'b'], tree.decode_all(Cursor::new(vec![0b_01000000]), 2).unwrap());
+    }
+
+    #[test]
+    fn map_tree_symbols() {
+        let tree = HuffBuilder::<u8, u32>::new()
+            .add(0, 1)
+            .add(1, 2)
+            .add(2, 10)
+            .build()
+            .unwrap();
+
+        let names = ["zero", "one", "two"];
+        let mapped = tree.map_symbols(|i| names[i as usize]);
+
+        let mut expected = HashMap::new();
+        expected.insert("two", vec![false]);
+        expected.insert("one", vec![true, false]);
+        expected.insert("zero", vec![true, true]);
+
+        assert_eq!(expected, mapped.encoding());
+    }
+
+    #[test]
+    fn ascii_tree() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let expected = concat!(
+            "*\n",
+            "|-- 0: 'd'\n",
+            "`-- 1\n",
+            "    |-- 10: 'b'\n",
+            "    `-- 11: 'a'\n",
+        );
+
+        assert_eq!(expected, tree.to_ascii_tree());
+        assert_eq!("0: 'a'\n", HuffTree::new_leaf('a').to_ascii_tree());
+    }
+
+    #[test]
+    #[allow(clippy::useless_vec)]
+    fn encode() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 1)
+            .add('c', 1)
+            .add('d', 1)
+            .build()
+            .unwrap();
+
+        let mut output: Vec<u8> = vec![];
+        {
+            let mut writer = HuffWriter::new(tree, &mut output);
+
+            for value in vec!['a', 'b', 'c', 'd', 'a'] {
+                writer.write(&value).unwrap();
+            }
+        }
+
+        let expected = vec![0b_00011011, 0b_00000000];
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    #[allow(clippy::useless_vec, clippy::single_match)]
+    fn encode_value_error() {
+        let tree = HuffBuilder::<char, u32>::new().add('a', 1).build().unwrap();
+
+        let mut output: Vec<u8> = vec![];
+        {
+            let mut writer = HuffWriter::new(tree, &mut output);
+
+            for value in vec!['b'] {
+                match writer.write(&value) {
+                    Ok(_) => panic!(),
+                    Err(_) => (),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_symbol_names_symbol() {
+        let tree = HuffBuilder::<char, u32>::new().add('a', 1).build().unwrap();
+        let mut writer = HuffWriter::new(tree, vec![]);
+
+        let err = writer.write(&'z').unwrap_err();
+        let huff_err = err.get_ref().and_then(|e| e.downcast_ref::<HuffError>());
+
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        assert_eq!(Some(&HuffError::UnknownSymbol("'z'".to_string())), huff_err);
+        assert_eq!("symbol 'z' is not in the tree", err.to_string());
+    }
+
+
+    #[test]
+    fn decode() {
+
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 1)
+            .add('c', 1)
+            .add('d', 1)
+            .build()
+            .unwrap();
+
+        let input = vec![0b_00011011, 0b_00000000];
+
+        let mut reader = HuffReader::new(tree, Cursor::new(input));
+        let mut output = vec![];
+
+        for _ in 0..5 {
+            output.push(reader.read().unwrap());
+        }
+
+        assert_eq!(vec!['a', 'b', 'c', 'd', 'a'], output);
+    }
+
+    #[test]
+    fn decode_all_stops_at_clean_eof() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 1)
+            .add('c', 1)
+            .add('d', 1)
+            .build()
+            .unwrap();
+
+        let input = vec![0b_00011011];
+
+        let output = tree.decode_all(Cursor::new(input), 10).unwrap();
+
+        assert_eq!(vec!['a', 'b', 'c', 'd'], output);
+    }
+
+    #[test]
+    fn codes_longer_than_a_write() {
+        // a 70 bit code has to be written in more than one run
+        let mut tree = HuffTree::new_leaf(0u32);
+        for i in 1..71 {
+            tree = HuffTree::new_node(HuffTree::new_leaf(i), tree);
+        }
+        assert_eq!(70, tree.max_code_length());
+
+        let input = vec![0, 3, 0, 70, 0];
+        let mut writer = HuffWriter::new(tree.clone(), vec![]);
+        writer.write_all(&input).unwrap();
+        let output = writer.finish().unwrap();
+
+        // 70 + 68 + 70 + 1 + 70 bits
+        assert_eq!(35, output.len());
+        assert_eq!(input, tree.decode_all(Cursor::new(output), input.len()).unwrap());
+    }
+
+    #[test]
+    fn writer_shares_bit_writer() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let mut bits = BitWriter::new(vec![]);
+        bits.write_bit(true).unwrap();
+
+        let mut writer = HuffWriter::from_bit_writer(tree, bits);
+        writer.write_all(&['a', 'd']).unwrap();
+
+        let mut bits = writer.into_bit_writer();
+        bits.write_bit(true).unwrap();
+
+        assert_eq!(vec![0b_11101000], bits.finish().unwrap());
+    }
+
+    struct FailingWriter;
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(Error::from(ErrorKind::BrokenPipe))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn finish_reports_flush_error() {
+        let tree = HuffTree::new_node(HuffTree::new_leaf('a'), HuffTree::new_leaf('b'));
+        let mut writer = HuffWriter::new(tree, FailingWriter);
+        writer.write(&'a').unwrap();
+
+        assert_eq!(ErrorKind::BrokenPipe, writer.finish().err().unwrap().kind());
+    }
+
+    #[test]
+    #[should_panic(expected = "dropped without `finish`")]
+    #[cfg(debug_assertions)]
+    fn drop_asserts_on_lost_bits() {
+        let tree = HuffTree::new_node(HuffTree::new_leaf('a'), HuffTree::new_leaf('b'));
+        let mut writer = HuffWriter::new(tree, FailingWriter);
+        writer.write(&'a').unwrap();
+    }
+
+    #[test]
+    fn aligned_segments() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let mut writer = HuffWriter::new(tree.clone(), vec![]);
+        assert_eq!(0, writer.bit_position());
+        writer.write_all(&['a', 'd']).unwrap();
+        assert_eq!(3, writer.bit_position());
+        assert_eq!(5, writer.align_to_byte().unwrap());
+        assert_eq!(0, writer.bit_position());
+        assert_eq!(0, writer.align_to_byte().unwrap());
+        writer.write_all(&['b', 'b']).unwrap();
+        assert_eq!(4, writer.bit_position());
+        let output = writer.finish().unwrap();
+
+        assert_eq!(vec![0b_11000000, 0b_10100000], output);
+
+        let mut reader = HuffReader::new(tree, Cursor::new(output));
+        assert_eq!('a', reader.read().unwrap());
+        assert_eq!('d', reader.read().unwrap());
+        assert_eq!(5, reader.skip_to_byte().unwrap());
+        assert_eq!(0, reader.skip_to_byte().unwrap());
+        assert_eq!('b', reader.read().unwrap());
+        assert_eq!('b', reader.read().unwrap());
+    }
+
+    #[test]
+    fn indexed_writer_matches_map() {
+        let data = b"abracadabra";
+        let tree = HuffBuilder::from_bytes(data).build().unwrap();
+
+        let mut writer = HuffWriter::new(tree.clone(), vec![]);
+        writer.write_all(data).unwrap();
+        let expected = writer.finish().unwrap();
+
+        let mut writer = HuffWriter::new_indexed(tree.clone(), vec![]);
+        writer.write_all(data).unwrap();
+        assert_eq!(tree.encoded_bit_len(data).unwrap(), writer.encoded_bit_len(data).unwrap());
+
+        // below and above the largest symbol in the table
+        assert_eq!(ErrorKind::InvalidInput, writer.write(&b'e').unwrap_err().kind());
+        assert_eq!(ErrorKind::InvalidInput, writer.write(&b'z').unwrap_err().kind());
+
+        assert_eq!(expected, writer.finish().unwrap());
+    }
+
+    #[test]
+    fn writer_code_for() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+        let encoding = tree.encoding_ref();
+
+        let writer = HuffWriter::new(tree, vec![]);
+        for (value, code) in &encoding {
+            assert_eq!(Some(code), writer.code_for(value).as_ref());
+        }
+        assert_eq!(None, writer.code_for(&'z'));
+
+        let long: Vec<bool> = (0..70).map(|i| i % 3 == 0).collect();
+        assert_eq!(long, PackedCode::new(&long).to_bits());
+    }
+
+    #[test]
+    fn writer_new_limited() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('c', 3)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let err = HuffWriter::new_limited(tree.clone(), vec![], 2).err().unwrap();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        assert_eq!(
+            Some(&HuffError::CodeTooLong(2)),
+            err.get_ref().and_then(|e| e.downcast_ref::<HuffError>())
+        );
+
+        let mut writer = HuffWriter::new_limited(tree, vec![], 3).unwrap();
+        writer.write_all(&['a', 'd']).unwrap();
+        assert_eq!(vec![0b_10100000], writer.finish().unwrap());
+    }
+
+    #[test]
+    fn writer_stats() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let mut writer = HuffWriter::new(tree.clone(), vec![]);
+        writer.write(&'a').unwrap();
+        assert_eq!(None, writer.stats());
+
+        let mut writer = HuffWriter::new(tree, vec![]).with_stats();
+        writer.write_all(&['a', 'd', 'd']).unwrap();
+        writer.write(&'d').unwrap();
+        assert!(writer.write(&'z').is_err());
+
+        let stats = writer.stats().unwrap();
+        assert_eq!(4, stats.symbols);
+        assert_eq!(5, stats.total_bits);
+        assert_eq!(Some(&1), stats.counts.get(&'a'));
+        assert_eq!(Some(&3), stats.counts.get(&'d'));
+        assert_eq!(None, stats.counts.get(&'b'));
+    }
+
+    #[test]
+    fn writer_reader_reset() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let mut writer = HuffWriter::with_order(tree.clone(), vec![], BitOrder::LsbFirst);
+        writer.write_all(&['a', 'b']).unwrap();
+        let first = writer.reset(vec![]).unwrap();
+        writer.write_all(&['d', 'a']).unwrap();
+        let second = writer.finish().unwrap();
+
+        assert_eq!(vec![0b_00000111], first);
+        assert_eq!(vec![0b_00000110], second);
+
+        let mut reader = HuffReader::with_order(tree, Cursor::new(first), BitOrder::LsbFirst);
+        assert_eq!('a', reader.read().unwrap());
+        assert_eq!(Some('b'), reader.peek().unwrap());
+
+        let first = reader.reset(Cursor::new(second));
+        assert_eq!(1, first.position());
+        assert_eq!(0, reader.bits_read());
+        assert_eq!('d', reader.read().unwrap());
+        assert_eq!('a', reader.read().unwrap());
+    }
+
+    // Hands out its chunks one call at a time, blocking between them.
+    struct Trickle(Vec<Vec<u8>>);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.0.first_mut() {
+                Some(chunk) if chunk.is_empty() => {
+                    self.0.remove(0);
+                    Err(Error::from(ErrorKind::WouldBlock))
+                }
+                Some(chunk) => {
+                    buf[0] = chunk.remove(0);
+                    Ok(1)
+                }
+                None => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn reader_try_read() {
+        let builder = || {
+            HuffBuilder::<char, u32>::new()
+                .add('a', 1)
+                .add('b', 2)
+                .add('c', 3)
+                .add('d', 10)
+        };
+
+        // `d a b c a` as `0 101 100 11 101`, with `c` spanning the blocked
+        // read
+        let input = || Trickle(vec![vec![0b_01011001], vec![0b_11010000]]);
+        let tree = builder().build().unwrap();
+        let (table, _) = tree.build_decode_table().unwrap();
+
+        let readers = vec![
+            HuffReader::new(tree, input()),
+            HuffReader::from_decode_table(table, input()),
+        ];
+
+        for mut reader in readers {
+            let mut values = vec![];
+            let mut blocked = 0;
+            while values.len() < 5 {
+                match reader.try_read().unwrap() {
+                    Some(value) => values.push(value),
+                    None => blocked += 1,
+                }
+            }
+
+            assert_eq!(vec!['d', 'a', 'b', 'c', 'a'], values);
+            assert_eq!(1, blocked);
+            assert_eq!(12, reader.bits_read());
+        }
+    }
+
+    #[test]
+    fn reader_read_or() {
+        // a lone leaf's only code is `0`
+        let tree = HuffTree::new_leaf('a');
+        let (table, _) = tree.build_decode_table().unwrap();
+
+        let input = vec![0b_01000000, 0b_00000000];
+        let readers = vec![
+            HuffReader::new(tree, Cursor::new(input.clone())),
+            HuffReader::from_decode_table(table, Cursor::new(input)),
+        ];
+
+        for mut reader in readers {
+            assert_eq!('a', reader.read_or('?').unwrap());
+            assert_eq!('?', reader.read_or('?').unwrap());
+            assert_eq!(8, reader.bits_read());
+            assert_eq!('a', reader.read_or('?').unwrap());
+        }
+    }
+
+    #[test]
+    fn reader_read_traced() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('c', 3)
+            .add('d', 10)
+            .build()
+            .unwrap();
+        let (table, _) = tree.build_decode_table().unwrap();
+
+        // `d a c` as `0 101 11` and padding
+        let input = vec![0b_01011100];
+        let readers = vec![
+            HuffReader::new(tree, Cursor::new(input.clone())),
+            HuffReader::from_decode_table(table, Cursor::new(input)),
+        ];
+
+        for mut reader in readers {
+            assert_eq!(('d', vec![false]), reader.read_traced().unwrap());
+            assert_eq!(('a', vec![true, false, true]), reader.read_traced().unwrap());
+            assert_eq!(('c', vec![true, true]), reader.read_traced().unwrap());
+            assert_eq!(6, reader.bits_read());
+        }
+    }
+
+    #[test]
+    fn reader_from_slice() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let data = vec![0b_11100000];
+        let mut reader = HuffReader::from_slice(tree, &data);
+        assert_eq!('a', reader.read().unwrap());
+        assert_eq!('b', reader.read().unwrap());
+
+        // the slice is left past the one byte read
+        assert!(reader.into_inner().is_empty());
+    }
+
+    #[test]
+    fn boxed_roundtrip() {
+        use std::sync::{Arc, Mutex};
+
+        // a sink that can still be looked at once the writer has it boxed
+        #[derive(Clone)]
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        struct Coder {
+            writer: BoxedHuffWriter<char>,
+        }
+
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let sink = Shared(Arc::new(Mutex::new(vec![])));
+        let mut coder = Coder {
+            writer: HuffWriter::new(tree.clone(), Box::new(sink.clone())),
+        };
+        coder.writer.write_all(&['a', 'b', 'd']).unwrap();
+        coder.writer.finish().unwrap();
+
+        let bytes = sink.0.lock().unwrap().clone();
+        let mut reader: BoxedHuffReader<char> = HuffReader::new(tree, Box::new(Cursor::new(bytes)));
+        let mut out = vec![];
+        assert_eq!(3, reader.read_n(3, &mut out).unwrap());
+        assert_eq!(vec!['a', 'b', 'd'], out);
+    }
+
+    #[test]
+    fn writer_from_table() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let mut writer = HuffWriter::from_table(tree.encoding_ref(), vec![]);
+        writer.write_all(&['a', 'b', 'd']).unwrap();
+
+        assert_eq!(vec![0b_11100000], writer.finish().unwrap());
+    }
+
+    #[test]
+    fn reader_at_clean_end() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        // `ab` then four bits of padding
+        let mut reader = HuffReader::new(tree.clone(), Cursor::new(vec![0b_11100000]));
+        assert!(!reader.at_clean_end().unwrap());
+        assert_eq!('a', reader.read().unwrap());
+        assert_eq!('b', reader.read().unwrap());
+        assert!(reader.at_clean_end().unwrap());
+        assert_eq!(4, reader.bits_read());
+
+        // `d` then the first bit of a cut off code
+        let mut reader = HuffReader::new(tree, Cursor::new(vec![0b_01000000]));
+        assert_eq!('d', reader.read().unwrap());
+        assert!(!reader.at_clean_end().unwrap());
+    }
+
+    #[test]
+    fn reader_from_decode_table() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('c', 3)
+            .add('d', 10)
+            .build()
+            .unwrap();
+        let (table, _) = tree.build_decode_table().unwrap();
+        assert_eq!(3, table.bits());
+
+        // ends with one bit of a three bit code
+        let input = vec![0b_01101110, 0b_11001010, 0b_11000000];
+
+        let mut expected = HuffReader::new(tree, Cursor::new(input.clone()));
+        let mut reader = HuffReader::from_decode_table(table, Cursor::new(input));
+        assert_eq!(expected.peek().unwrap(), reader.peek().unwrap());
+        assert_eq!(0, reader.bits_read());
+
+        loop {
+            match (expected.read(), reader.read()) {
+                (Ok(a), Ok(b)) => assert_eq!(a, b),
+                (Err(a), Err(b)) => {
+                    assert_eq!(ErrorKind::UnexpectedEof, b.kind());
+                    assert_eq!(a.kind(), b.kind());
+                    break;
+                }
+                (a, b) => panic!("{:?} != {:?}", a, b),
+            }
+            assert_eq!(expected.bits_read(), reader.bits_read());
+        }
+    }
+
+    #[test]
+    fn read_n_appends() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let mut reader = HuffReader::new(tree, Cursor::new(vec![0b_01101000]));
+        let mut out = vec!['x'];
+
+        assert_eq!(2, reader.read_n(2, &mut out).unwrap());
+        assert_eq!(4, reader.read_n(10, &mut out).unwrap());
+        assert_eq!(0, reader.read_n(10, &mut out).unwrap());
+        assert_eq!(vec!['x', 'd', 'a', 'd', 'b', 'd', 'd'], out);
+    }
+
+    #[test]
+    fn read_exact_symbols() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let mut reader = HuffReader::new(tree.clone(), Cursor::new(vec![0b_01101000]));
+
+        assert_eq!(vec!['d', 'a'], reader.read_exact(2).unwrap());
+        assert_eq!(vec!['d', 'b', 'd', 'd'], reader.read_exact(4).unwrap());
+        assert!(reader.read_exact(0).unwrap().is_empty());
+
+        // the input ends cleanly, but too soon
+        assert_eq!(ErrorKind::UnexpectedEof, reader.read_exact(1).unwrap_err().kind());
+
+        let mut reader = HuffReader::new(tree, Cursor::new(vec![0b_01101000]));
+        assert_eq!(ErrorKind::UnexpectedEof, reader.read_exact(7).unwrap_err().kind());
+    }
+
+    #[test]
+    fn read_all_bounded_limit() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 1)
+            .add('c', 1)
+            .add('d', 1)
+            .build()
+            .unwrap();
+
+        let input = vec![0b_00011011, 0b_11100100];
+
+        let mut reader = HuffReader::new(tree.clone(), Cursor::new(input.clone()));
+        let output = reader.read_all_bounded(8).unwrap();
+        assert_eq!(vec!['a', 'b', 'c', 'd', 'd', 'c', 'b', 'a'], output);
+
+        let mut reader = HuffReader::new(tree, Cursor::new(input));
+        let err = reader.read_all_bounded(7).unwrap_err();
+        let huff_err = err.get_ref().and_then(|e| e.downcast_ref::<HuffError>());
+        assert_eq!(Some(&HuffError::LimitExceeded(7)), huff_err);
+    }
+
+    #[test]
+    fn read_chars_into_string() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        // `dadbdd` fills the byte exactly
+        let mut reader = HuffReader::new(tree.clone(), Cursor::new(vec![0b_01101000]));
+        assert_eq!("dad", reader.read_string(3).unwrap());
+        assert_eq!("bdd", reader.read_string(10).unwrap());
+        assert_eq!("", reader.read_string(10).unwrap());
+
+        let mut reader = HuffReader::new(tree, Cursor::new(vec![0b_00000001]));
+        let err = reader.read_string(10).unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+    }
+
+    #[test]
+    fn read_until_eof_ignores_padding() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .with_eof('$')
+            .build()
+            .unwrap();
+
+        let mut writer = HuffWriter::new(tree.clone(), vec![]).with_eof('$');
+        writer.write_all(&['d', 'a', 'd', 'b']).unwrap();
+        writer.write_eof().unwrap();
+        let output = writer.finish().unwrap();
+
+        let mut reader = HuffReader::new(tree, Cursor::new(output)).with_eof('$');
+
+        assert_eq!(vec!['d', 'a', 'd', 'b'], reader.read_until_eof().unwrap());
+    }
+
+    #[test]
+    fn decode_all_partial_symbol_error() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let input = vec![0b_00000001];
+
+        match tree.decode_all(Cursor::new(input), 10) {
+            Ok(_) => panic!(),
+            Err(e) => assert_eq!(ErrorKind::UnexpectedEof, e.kind()),
+        }
+    }
+
+    #[test]
+    fn single_symbol_roundtrip() {
+        let tree = HuffBuilder::<char, u32>::new().add('a', 1).build().unwrap();
+
+        let mut output: Vec<u8> = vec![];
+        {
+            let mut writer = HuffWriter::new(tree.clone(), &mut output);
+
+            for value in &['a'; 5] {
+                writer.write(value).unwrap();
+            }
+        }
+
+        assert_eq!(vec![0b_00000000], output);
+
+        let decoded = tree.decode_all(Cursor::new(output), 5).unwrap();
+
+        assert_eq!(vec!['a'; 5], decoded);
+    }
+
+    #[test]
+    fn finish_returns_writer() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 1)
+            .add('c', 1)
+            .add('d', 1)
+            .build()
+            .unwrap();
+
+        let mut writer = HuffWriter::new(tree, vec![]);
+
+        for value in &['a', 'b', 'c', 'd', 'a', 'd'] {
+            writer.write(value).unwrap();
+        }
+
+        let output = writer.finish().unwrap();
+
+        assert_eq!(vec![0b_00011011, 0b_00110000], output);
+    }
+
+    #[test]
+    fn code_length_bounds() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('c', 4)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        assert_eq!(3, tree.max_code_length());
+        assert_eq!(1, tree.min_code_length());
+
+        let leaf = HuffTree::new_leaf('a');
+
+        assert_eq!(1, leaf.max_code_length());
+        assert_eq!(1, leaf.min_code_length());
+    }
+
+    #[test]
+    fn build_limited_caps_code_length() {
+        let weights = [1, 1, 2, 3, 5, 8, 13, 21, 34, 55];
+
+        let builder = || {
+            weights
+                .iter()
+                .enumerate()
+                .fold(HuffBuilder::<usize, u32>::new(), |b, (i, w)| b.add(i, *w))
+        };
+
+        assert_eq!(9, builder().build().unwrap().max_code_length());
+
+        let tree = builder().build_limited(4).unwrap();
+
+        assert!(tree.max_code_length() <= 4);
+        assert_eq!(weights.len(), tree.encoding().len());
+
+        assert_eq!(None, builder().build_limited(3));
+    }
+
+    #[test]
+    fn build_limited_duplicate_symbols() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 5)
+            .add('a', 5)
+            .add('b', 1)
+            .add('c', 1)
+            .build_limited(8)
+            .unwrap();
+
+        let expected = HuffBuilder::new().add('a', 10).add('b', 1).add('c', 1).build();
+        assert_eq!(3, tree.count_leaves());
+        assert_eq!(expected, Some(tree));
+
+        // ties are broken by symbol, not the order symbols were added in
+        let forward = HuffBuilder::<char, u32>::new().add('a', 1).add('b', 1).add('c', 1);
+        let backward = HuffBuilder::<char, u32>::new().add('c', 1).add('b', 1).add('a', 1);
+        assert_eq!(forward.build_limited(2), backward.build_limited(2));
+    }
+
+    #[test]
+    fn canonical_encoding_map() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let mut expected = HashMap::new();
+        expected.insert('d', vec![false]);
+        expected.insert('a', vec![true, false]);
+        expected.insert('b', vec![true, true]);
+
+        assert_eq!(expected, tree.canonical_encoding());
+    }
+
+    #[test]
+    fn tree_into_canonical() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+        let canonical = tree.canonical_encoding();
+
+        let tree = tree.into_canonical();
+        assert_eq!(canonical, tree.encoding_ref());
+        assert_eq!(tree, tree.clone().into_canonical());
+
+        // mirror images have the same lengths
+        let bc = HuffTree::new_node(HuffTree::new_leaf('b'), HuffTree::new_leaf('c'));
+        let cb = HuffTree::new_node(HuffTree::new_leaf('c'), HuffTree::new_leaf('b'));
+        let left = HuffTree::new_node(HuffTree::new_leaf('a'), bc);
+        let right = HuffTree::new_node(cb, HuffTree::new_leaf('a'));
+
+        assert_eq!(left.clone(), right.into_canonical());
+        assert_eq!(left.clone(), left.into_canonical());
+
+        let leaf = HuffTree::new_leaf('a');
+        assert_eq!(leaf.clone(), leaf.into_canonical());
+    }
+
+    #[test]
+    fn code_lengths_in_tree_order() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        assert_eq!(Some(vec![('d', 1), ('b', 2), ('a', 2)]), tree.code_lengths());
+        assert_eq!(Some(vec![('a', 1)]), HuffTree::new_leaf('a').code_lengths());
+
+        let mut deep = HuffTree::new_leaf(0u32);
+        for i in 1..257 {
+            deep = HuffTree::new_node(HuffTree::new_leaf(i), deep);
+        }
+        assert_eq!(None, deep.code_lengths());
+    }
+
+    #[test]
+    fn tree_from_code_lengths() {
+        let tree = HuffTree::from_code_lengths(&[('b', 2), ('x', 0), ('a', 2), ('d', 1)]).unwrap();
+
+        let mut expected = HashMap::new();
+        expected.insert('d', vec![false]);
+        expected.insert('a', vec![true, false]);
+        expected.insert('b', vec![true, true]);
+
+        assert_eq!(expected, tree.encoding());
+
+        // over and under filling the code space
+        assert_eq!(None, HuffTree::from_code_lengths(&[('a', 1), ('b', 1), ('c', 1)]));
+        assert_eq!(None, HuffTree::from_code_lengths(&[('a', 1), ('b', 2)]));
+
+        // lengths past what the symbols could fill are turned down up front
+        assert_eq!(None, HuffTree::from_code_lengths(&[('a', 1), ('b', 10_000_000)]));
+        assert_eq!(None, HuffTree::from_code_lengths(&[('a', 1), ('b', usize::MAX)]));
+
+        // a valid spine is built without recursing once per level
+        let n = 100_000;
+        let mut spine: Vec<(u32, usize)> = (1..n).map(|i| (i, i as usize)).collect();
+        spine.push((n, n as usize - 1));
+        HuffTree::from_code_lengths(&spine).unwrap().drop_deep();
+    }
+
+    #[test]
+    fn serialize_tree() {
+        let tree = HuffBuilder::<u8, u32>::new()
+            .add(b'a', 1)
+            .add(b'b', 2)
+            .add(b'd', 10)
+            .build()
+            .unwrap();
+
+        let mut output = vec![];
+        tree.serialize(&mut output).unwrap();
+
+        assert_eq!(vec![0b_10100000, b'd', b'b', b'a'], output);
+    }
+
+    #[test]
+    fn serialize_roundtrip() {
+        let tree = HuffBuilder::<u8, u32>::new()
+            .add(0, 1)
+            .add(1, 1)
+            .add(2, 2)
+            .add(3, 3)
+            .add(4, 5)
+            .add(5, 8)
+            .add(6, 13)
+            .add(7, 21)
+            .add(8, 34)
+            .build()
+            .unwrap();
+
+        let mut output = vec![];
+        tree.serialize(&mut output).unwrap();
+        output.extend_from_slice(b"rest");
+
+        let mut input = Cursor::new(output);
+        let decoded = HuffTree::deserialize(&mut input).unwrap();
+
+        assert_eq!(tree, decoded);
+
+        let mut rest = vec![];
+        input.read_to_end(&mut rest).unwrap();
+        assert_eq!(b"rest".to_vec(), rest);
+    }
+
+    #[test]
+    fn encode_slice_bit_length() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let (output, bit_len) = tree.encode_slice(&['d', 'b', 'a', 'd']).unwrap();
+
+        assert_eq!(vec![0b_01011000], output);
+        assert_eq!(6, bit_len);
+
+        let err = tree.encode_slice(&['d', 'x']).unwrap_err();
+
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        assert!(err.to_string().contains("'x'"));
+    }
+
+    #[test]
+    fn string_symbols_roundtrip() {
+        let words = vec!["the".to_owned(), "cat".to_owned(), "the".to_owned(), "hat".to_owned()];
+
+        let tree = HuffBuilder::<String, u32>::new()
+            .add("the".to_owned(), 2)
+            .add("cat".to_owned(), 1)
+            .add("hat".to_owned(), 1)
+            .build()
+            .unwrap();
+
+        let mut output: Vec<u8> = vec![];
+        {
+            let mut writer = HuffWriter::new(tree.clone(), &mut output);
+
+            for word in &words {
+                writer.write(word).unwrap();
+            }
+        }
+
+        let mut reader = HuffReader::new(tree, Cursor::new(output));
+        let mut decoded = vec![];
+
+        for _ in 0..words.len() {
+            decoded.push(reader.read().unwrap());
+        }
+
+        assert_eq!(words, decoded);
+    }
+
+    #[test]
+    fn reader_iterator() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 1)
+            .add('c', 1)
+            .add('d', 1)
+            .build()
+            .unwrap();
+
+        let reader = HuffReader::new(tree.clone(), Cursor::new(vec![0b_00011011]));
+        let output = reader.collect::<std::io::Result<Vec<_>>>().unwrap();
+
+        assert_eq!(vec!['a', 'b', 'c', 'd'], output);
+
+        let mut reader = HuffReader::new(tree, Cursor::new(vec![0b_00011011]));
+        let output = reader.by_ref().take(2).collect::<std::io::Result<Vec<_>>>().unwrap();
+
+        assert_eq!(vec!['a', 'b'], output);
+        assert_eq!('c', reader.read().unwrap());
+    }
+
+    #[test]
+    fn reader_iterator_partial_symbol() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let mut reader = HuffReader::new(tree, Cursor::new(vec![0b_00000001]));
+
+        for _ in 0..7 {
+            assert_eq!('d', reader.next().unwrap().unwrap());
+        }
+
+        let err = reader.next().unwrap().unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+    }
+
+    #[test]
+    fn try_build_empty() {
+        let result = HuffBuilder::<char, u32>::new().try_build();
+
+        assert_eq!(Err(BuildError::Empty), result);
+    }
+
+    #[test]
+    fn deep_tree_drop() {
+        let mut tree = HuffTree::new_leaf(0u32);
+        for i in 1..100_000 {
+            tree = HuffTree::new_node(tree, HuffTree::new_leaf(i));
+        }
+
+        let mut right = HuffTree::new_leaf(0u32);
+        for i in 1..100_000 {
+            right = HuffTree::new_node(HuffTree::new_leaf(i), right);
+        }
+
+        let mut zigzag = HuffTree::new_leaf(0u32);
+        for i in 1..100_000 {
+            zigzag = match i % 2 {
+                0 => HuffTree::new_node(zigzag, HuffTree::new_leaf(i)),
+                _ => HuffTree::new_node(HuffTree::new_leaf(i), zigzag),
+            };
+        }
+
+        tree.drop_deep();
+        right.drop_deep();
+        zigzag.drop_deep();
+    }
+
+    #[test]
+    fn deep_tree_traversal() {
+        let mut tree = HuffTree::new_leaf(0u32);
+        for i in 1..100_000 {
+            tree = HuffTree::new_node(tree, HuffTree::new_leaf(i));
+        }
+
+        assert!(tree.contains(&0));
+        assert!(!tree.contains(&100_000));
+        assert_eq!(100_000, tree.count_leaves());
+        assert_eq!(99_999, tree.count_nodes());
+        assert_eq!(99_999, tree.max_code_length());
+        assert_eq!(1, tree.min_code_length());
+        assert_eq!(Some(&0), tree.symbols().first());
+        assert!(tree.code_lengths().is_none());
+
+        let mut bytes = vec![];
+        tree.serialize_with(&mut bytes, |w, v| w.write_all(&v.to_le_bytes())).unwrap();
+        assert!(!bytes.is_empty());
+
+        let tree = tree.map_symbols(|v| v + 1);
+        assert!(tree.contains(&100_000));
+        let tree = tree.prune(&1).unwrap();
+        assert_eq!(99_999, tree.count_leaves());
+        tree.drop_deep();
+    }
+
+    #[test]
+    fn reshape_for_drop() {
+        let leaf = HuffTree::new_leaf;
+        let mut tree = HuffTree::new_node(HuffTree::new_node(leaf('a'), leaf('b')), leaf('c'));
+
+        tree.rotate_right();
+        assert_eq!(HuffTree::new_node(leaf('a'), HuffTree::new_node(leaf('b'), leaf('c'))), tree);
+        tree.rotate_left();
+        assert_eq!(HuffTree::new_node(HuffTree::new_node(leaf('a'), leaf('b')), leaf('c')), tree);
+
+        let mut tree = HuffTree::new_leaf(0u32);
+        for i in 1..1000 {
+            tree = HuffTree::new_node(tree, HuffTree::new_leaf(i));
+        }
+        let symbols = tree.symbols();
+
+        assert_eq!(999, tree.make_vine());
+        assert_eq!(symbols, tree.symbols());
+        tree.fold_vine(999);
+        assert_eq!(symbols, tree.symbols());
+        assert!(!tree.deeper_than(11));
+    }
+
+    #[test]
+    fn deep_tree_encoding() {
+        // the codes of a spine take space growing with the square of its
+        // depth, which keeps this shallower than `deep_tree_drop`
+        let mut tree = HuffTree::new_leaf(0u32);
+        for i in 1..10_000 {
+            tree = HuffTree::new_node(HuffTree::new_leaf(i), tree);
+        }
+
+        let encoding = tree.encoding_ref();
+        assert_eq!(10_000, encoding.len());
+        assert_eq!(Some(&vec![false]), encoding.get(&9_999));
+        assert_eq!(9_999, encoding[&0].len());
+
+        tree.drop_deep();
+    }
+
+    #[test]
+    fn tree_codes() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('c', 3)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let codes: Vec<(char, Vec<bool>)> = tree.codes().collect();
+        assert_eq!(tree.symbols(), codes.iter().map(|c| c.0).collect::<Vec<_>>());
+        assert_eq!(tree.encoding_ref(), codes.into_iter().collect());
+
+        let leaf = HuffTree::new_leaf('a');
+        assert_eq!(vec![('a', vec![false])], leaf.codes().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn tree_packed_codes() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let expected = vec![('d', 0b_0, 1), ('b', 0b_10, 2), ('a', 0b_11, 2)];
+        assert_eq!(Ok(expected), tree.packed_codes());
+        assert_eq!(Ok(vec![('a', 0, 1)]), HuffTree::new_leaf('a').packed_codes());
+
+        let mut deep = HuffTree::new_leaf(0u32);
+        for i in 1..33 {
+            deep = HuffTree::new_node(deep, HuffTree::new_leaf(i));
+        }
+        assert_eq!(Some(&(0, 0, 32)), deep.packed_codes().unwrap().first());
+        assert_eq!(Some(&(32, 1, 1)), deep.packed_codes().unwrap().last());
+
+        let deeper = HuffTree::new_node(deep, HuffTree::new_leaf(33));
+        assert_eq!(Err(TreeError::CodeTooLong(32)), deeper.packed_codes());
+    }
+
+    #[test]
+    fn sorted_encoding() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('c', 3)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let expected = vec![
+            ('d', vec![false]),
+            ('c', vec![true, true]),
+            ('a', vec![true, false, true]),
+            ('b', vec![true, false, false]),
+        ];
+        assert_eq!(expected, tree.sorted_encoding());
+
+        assert_eq!(vec![('a', vec![false])], HuffTree::new_leaf('a').sorted_encoding());
+    }
+
+    #[test]
+    fn prune_symbol() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('c', 4)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let pruned = tree.clone().prune(&'b').unwrap();
+        assert_eq!(vec!['d', 'c', 'a'], pruned.symbols());
+        assert_eq!(Some(vec![('d', 1), ('c', 2), ('a', 2)]), pruned.code_lengths());
+
+        let codec = HuffCodec::new(pruned.clone());
+        let data = ['a', 'c', 'd', 'a', 'd'];
+        let bits = codec.encode(&data).unwrap();
+        assert_eq!(Some(data.to_vec()), codec.decode(&bits, data.len()));
+
+        assert_eq!(tree.clone(), tree.clone().prune(&'z').unwrap());
+        assert_eq!(None, HuffTree::new_leaf('a').prune(&'a'));
+        assert_eq!(Some(HuffTree::new_leaf('d')), pruned.prune(&'a').unwrap().prune(&'c'));
+    }
+
+    #[test]
+    fn tree_as_map_key() {
+        let build = |text: &str| {
+            let chars: Vec<char> = text.chars().collect();
+            HuffBuilder::from_symbols(&chars).build()
+        };
+
+        let mut cache = HashMap::new();
+        cache.insert(build("abracadabra").unwrap(), 1);
+        cache.insert(build("hello").unwrap(), 2);
+
+        // the same counts in another order build the same tree
+        assert_eq!(Some(&1), cache.get(&build("aaaaabbrrcd").unwrap()));
+        assert_eq!(Some(&2), cache.get(&build("olleh").unwrap()));
+        assert_eq!(None, cache.get(&build("abc").unwrap()));
+    }
+
+    #[test]
+    fn fixed_tree() {
+        let tree = HuffTree::fixed(vec!['a', 'b', 'c', 'd']).unwrap();
+        assert_eq!(vec!['a', 'b', 'c', 'd'], tree.symbols());
+        assert_eq!(vec![0, 0, 4], tree.depth_histogram());
+
+        let tree = HuffTree::fixed("abcde".chars()).unwrap();
+        assert_eq!(vec!['a', 'b', 'c', 'd', 'e'], tree.symbols());
+        assert_eq!((2, 3), (tree.min_code_length(), tree.max_code_length()));
+
+        assert_eq!(Some(HuffTree::new_leaf('a')), HuffTree::fixed(Some('a')));
+        assert_eq!(None, HuffTree::fixed(Vec::<char>::new()));
+    }
+
+    #[test]
+    fn build_uniform_if_pow2() {
+        let builder = || {
+            HuffBuilder::<char, u32>::new()
+                .add('d', 10)
+                .add('a', 1)
+                .add('c', 1)
+                .add('b', 2)
+                .add('a', 1)
+        };
+
+        let tree = builder().build_uniform_if_pow2().unwrap();
+        assert_eq!(HuffTree::fixed("abcd".chars()), Some(tree));
+
+        let tree = builder().add('e', 1).build_uniform_if_pow2();
+        assert_eq!(builder().add('e', 1).build(), tree);
+
+        let tree = HuffBuilder::<char, f64>::new().add('a', f64::NAN).add('b', 1.0);
+        assert_eq!(HuffTree::fixed("ab".chars()), tree.build_uniform_if_pow2());
+
+        assert_eq!(None, HuffBuilder::<char, u32>::new().build_uniform_if_pow2());
+    }
+
+    #[test]
+    fn builder_with_capacity() {
+        let builder = HuffBuilder::<u16, u32>::with_capacity(1000);
+        assert!(builder.nodes.capacity() >= 1000);
+
+        let builder = HuffBuilder::with_capacity(0).add_table((0..1000u16).map(|v| (v, 1u32)));
+        assert_eq!(1000, builder.nodes.capacity());
+
+        let symbols: Vec<u16> = (0..1000).collect();
+        let builder = HuffBuilder::with_capacity(0).add_parallel(&symbols, &[1u32; 1000]);
+        assert_eq!(1000, builder.nodes.capacity());
+    }
+
+    #[test]
+    fn builder_from_iter() {
+        let table = [('a', 1), ('b', 2), ('d', 10)];
+        let expected = HuffBuilder::new().add_table(table.iter().cloned()).build();
+
+        let builder: HuffBuilder<char, u32> = table.iter().cloned().collect();
+        assert_eq!(expected, builder.build());
+    }
+
+    #[test]
+    fn builder_try_from_slice() {
+        let table = [('a', 1), ('b', 2), ('d', 10)];
+        let expected = HuffBuilder::new().add_table(table.iter().cloned()).build();
+
+        assert_eq!(expected, HuffBuilder::try_from(&table[..]).unwrap().build());
+
+        let empty: &[(char, u32)] = &[];
+        assert_eq!(Err(BuildError::Empty), HuffBuilder::try_from(empty).map(|_| ()));
+    }
+
+    #[test]
+    fn builder_observe() {
+        let mut builder = HuffBuilder::new();
+        for c in "abracadabra".chars() {
+            builder.observe(c);
+        }
+        builder = builder.add('a', 1);
+
+        let expected = HuffBuilder::new()
+            .add('a', 6)
+            .add('b', 2)
+            .add('r', 2)
+            .add('c', 1)
+            .add('d', 1);
+
+        // one entry per distinct observed symbol, then the added one
+        assert_eq!(6, builder.nodes.len());
+        assert_eq!(expected.build(), builder.build());
+    }
+
+    #[test]
+    fn builder_from_symbols() {
+        let data: Vec<u16> = vec![1000, 7, 1000, 65535, 1000, 7];
+        let builder = HuffBuilder::from_symbols(&data);
+
+        assert_eq!(vec![(1000, 3), (7, 2), (65535, 1)], builder.nodes);
+
+        let expected = HuffBuilder::new().add(7u16, 2).add(1000, 3).add(65535, 1).build();
+        assert_eq!(expected, builder.build());
+
+        let builder = HuffBuilder::from_symbols(b"abracadabra");
+        assert_eq!(HuffBuilder::from_bytes(b"abracadabra").build(), builder.build());
+
+        assert_eq!(None, HuffBuilder::<u32, usize>::from_symbols(&[]).build());
+    }
+
+    #[test]
+    fn builder_from_counts_smoothed() {
+        let mut counts = Map::new();
+        counts.insert('a', 5);
+        counts.insert('b', 2);
+        counts.insert('z', 1);
+
+        let mut builder = HuffBuilder::from_counts_smoothed(&counts, &['a', 'b', 'c', 'a'], 1);
+        assert_eq!(vec![('a', 6), ('b', 3), ('c', 1), ('z', 2)], builder.nodes);
+
+        builder.observe('c');
+        assert_eq!(('c', 2), builder.nodes[2]);
+
+        let mut symbols = builder.build().unwrap().symbols();
+        symbols.sort();
+        assert_eq!(vec!['a', 'b', 'c', 'z'], symbols);
+
+        // unseen symbols still get a code without smoothing
+        let tree = HuffBuilder::from_counts_smoothed(&counts, &['c'], 0).build().unwrap();
+        assert!(tree.contains(&'c'));
+    }
+
+    #[test]
+    fn build_all_zero_weights() {
+        for n in 1..10u8 {
+            let symbols: Vec<u8> = (0..n).collect();
+            let zero = symbols.iter().fold(HuffBuilder::<u8, u32>::new(), |b, &v| b.add(v, 0));
+            let equal = symbols.iter().fold(HuffBuilder::<u8, u32>::new(), |b, &v| b.add(v, 1));
+
+            let tree = zero.build().unwrap();
+            assert_eq!(equal.build().unwrap(), tree);
+            assert!(tree.max_code_length() - tree.min_code_length() <= 1);
+            assert_eq!(HuffTree::fixed(symbols).unwrap().depth_histogram(), tree.depth_histogram());
+        }
+    }
+
+    #[test]
+    fn builder_merge() {
+        let mut first = HuffBuilder::new();
+        let mut second = HuffBuilder::new();
+        for c in "abra".chars() {
+            first.observe(c);
+        }
+        for c in "cadabra".chars() {
+            second.observe(c);
+        }
+
+        let mut merged = first.merge(second.add('e', 1));
+        merged.observe('d');
+        merged.observe('e');
+
+        let expected = HuffBuilder::new()
+            .add('a', 5)
+            .add('b', 2)
+            .add('r', 2)
+            .add('c', 1)
+            .add('d', 2)
+            .add('e', 2);
+
+        assert_eq!(expected.build(), merged.build());
+    }
+
+    #[test]
+    fn builder_add_parallel() {
+        let expected = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build();
+
+        let tree = HuffBuilder::new().add_parallel(&['a', 'b', 'd'], &[1u32, 2, 10]).build();
+        assert_eq!(expected, tree);
+    }
+
+    #[test]
+    #[should_panic(expected = "3 symbols were given with 2 weights")]
+    fn builder_add_parallel_mismatch() {
+        let _ = HuffBuilder::new().add_parallel(&['a', 'b', 'd'], &[1u32, 2]);
+    }
+
+    #[test]
+    fn build_nan_weight() {
+        let builder = || {
+            HuffBuilder::<char, f64>::new()
+                .add('a', 1.0)
+                .add('b', f64::NAN)
+                .add('c', 2.0)
+        };
+
+        assert_eq!(Err(BuildError::InvalidWeight), builder().try_build());
+        assert_eq!(Err(BuildError::InvalidWeight), builder().build_presorted());
+        assert_eq!(None, builder().build_limited(4));
+
+        // infinities are still ordered, so they build
+        let tree = HuffBuilder::<char, f64>::new()
+            .add('a', 1.0)
+            .add('b', f64::INFINITY)
+            .build()
+            .unwrap();
+        assert_eq!(vec!['b', 'a'], tree.symbols());
+    }
+
+    #[test]
+    fn build_ignores_insertion_order() {
+        let first = HuffBuilder::<char, u32>::new()
+            .add('c', 1)
+            .add('a', 1)
+            .add('b', 2)
+            .add('e', 2)
+            .add('d', 1)
+            .build()
+            .unwrap();
+
+        let second = HuffBuilder::<char, u32>::new()
+            .add('e', 2)
+            .add('d', 1)
+            .add('b', 2)
+            .add('a', 1)
+            .add('c', 1)
+            .build()
+            .unwrap();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn read_into_buffer() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        let mut reader = HuffReader::new(tree, Cursor::new(vec![0b_01011001]));
+        let mut out = ['x'; 3];
+
+        assert_eq!(3, reader.read_into(&mut out).unwrap());
+        assert_eq!(['d', 'b', 'a'], out);
+
+        assert_eq!(2, reader.read_into(&mut out).unwrap());
+        assert_eq!(['d', 'd'], out[..2]);
+    }
+
+    #[test]
+    fn average_code_length() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 1)
+            .add('d', 2)
+            .build()
+            .unwrap();
+
+        let mut weights = HashMap::new();
+        weights.insert('a', 1u32);
+        weights.insert('b', 1);
+        weights.insert('d', 2);
+
+        assert_eq!(1.5, tree.average_code_length(&weights));
+
+        weights.remove(&'b');
+
+        assert_eq!(4.0 / 3.0, tree.average_code_length(&weights));
+    }
+
+    #[test]
+    fn savings_vs_fixed() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 1)
+            .add('c', 2)
+            .add('d', 4)
+            .build()
+            .unwrap();
+
+        let mut weights = HashMap::new();
+        weights.insert('a', 1u32);
+        weights.insert('b', 1);
+        weights.insert('c', 2);
+        weights.insert('d', 4);
+
+        // 1.75 bits a symbol against 2
+        assert_eq!(0.125, tree.savings_vs_fixed(&weights));
+
+        weights.insert('a', 100);
+        assert!(tree.savings_vs_fixed(&weights) < 0.0);
+
+        assert_eq!(0.0, tree.savings_vs_fixed(&HashMap::<char, u32>::new()));
+        let mut lone = HashMap::new();
+        lone.insert('a', 3u32);
+        assert_eq!(0.0, HuffTree::new_leaf('a').savings_vs_fixed(&lone));
+    }
+
+    #[test]
+    fn entropy_and_redundancy() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 1)
+            .add('d', 2)
+            .build()
+            .unwrap();
+
+        let mut weights = HashMap::new();
+        weights.insert('a', 1u32);
+        weights.insert('b', 1);
+        weights.insert('d', 2);
+
+        assert_eq!(1.5, shannon_entropy(&weights));
+        assert_eq!(0.0, tree.redundancy(&weights));
+
+        weights.insert('d', 6);
+        let redundancy = tree.redundancy(&weights);
+        assert!(redundancy > 0.0 && redundancy < 1.0);
+
+        assert_eq!(0.0, shannon_entropy(&HashMap::<char, u32>::new()));
+    }
+
+    #[test]
+    fn tree_stats() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 1)
+            .add('d', 2)
+            .build()
+            .unwrap();
+
+        let mut weights = HashMap::new();
+        weights.insert('a', 1u32);
+        weights.insert('b', 1);
+        weights.insert('d', 2);
+
+        // powers of two weights make the tree optimal
+        let stats = tree.weight_of(&weights);
+        assert_eq!(6.0, stats.total_bits);
+        assert_eq!(6.0, stats.entropy_bits);
+        assert_eq!(3, stats.symbols);
+
+        weights.insert('d', 6);
+        let stats = tree.weight_of(&weights);
+        assert_eq!(10.0, stats.total_bits);
+        assert!(stats.entropy_bits < stats.total_bits);
+    }
+
+    #[test]
+    fn tree_from_table_function() {
+        let mut table = HashMap::new();
+        table.insert('a', 1);
+        table.insert('b', 2);
+        table.insert('d', 10);
+
+        let expected = HuffTree::new_node(
+            HuffTree::new_leaf('d'),
+            HuffTree::new_node(HuffTree::new_leaf('b'), HuffTree::new_leaf('a')),
+        );
+
+        assert_eq!(Ok(expected.clone()), huff_tree_from_table(table));
+        assert_eq!(Ok(expected), huff_tree_from_slice(&[('b', 2), ('d', 10), ('a', 1)]));
+
+        assert_eq!(Err(BuildError::Empty), huff_tree_from_slice::<char, u32>(&[]));
+    }
+
+    #[test]
+    fn decode_garbage() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('c', 4)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        // arbitrary bits still land on a leaf within max_code_length bits
+        let reader = HuffReader::new(tree, Cursor::new(vec![0b_11011010, 0b_11111110]));
+        let output = reader.collect::<std::io::Result<Vec<_>>>().unwrap();
 
-impl<V: Eq + Copy> HuffTree<V> {
-    pub fn new_leaf(value: V) -> Self {
-        HuffTree::Leaf(value)
-    }
+        assert_eq!(vec!['b', 'b', 'c', 'a', 'a', 'c'], output);
 
-    pub fn new_node(left: Self, right: Self) -> Self {
-        HuffTree::Node(Box::new(left), Box::new(right))
+        let mut reader = HuffReader::new(HuffTree::new_leaf('a'), Cursor::new(vec![0b_01000000]));
+
+        assert_eq!('a', reader.read().unwrap());
+        assert_eq!(ErrorKind::InvalidData, reader.read().unwrap_err().kind());
     }
-}
 
-impl<V: Eq + Copy + Hash> HuffTree<V> {
-    pub fn encoding(self) -> HashMap<V, Vec<bool>> {
-        let trail: Vec<bool> = vec![];
-        let mut map = HashMap::new();
+    #[test]
+    fn read_bit_budget() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
 
-        self.build_map(trail, &mut map);
+        let mut reader = HuffReader::new(tree.clone(), Cursor::new(vec![0b_11010000]));
+        assert_eq!('a', reader.read().unwrap());
+        assert_eq!(2, reader.bits_read());
 
-        map
+        // a budget short of the tree stops the walk rather than reading on
+        reader.decoder = Decoder::Tree(Box::new(tree), 1);
+        assert_eq!('d', reader.read().unwrap());
+        assert_eq!(ErrorKind::InvalidData, reader.read().unwrap_err().kind());
     }
 
-    fn build_map(self, trail: Vec<bool>, map: &mut HashMap<V, Vec<bool>>) {
-        match self {
-            HuffTree::Leaf(v) => {
-                map.insert(v, trail.clone());
-            }
-            HuffTree::Node(l, r) => {
+    #[test]
+    fn lsb_first_roundtrip() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
 
-                //handle left
-                let mut left = trail.clone();
-                left.push(false);
-                l.build_map(left, map);
+        let input = ['d', 'd', 'b', 'a'];
 
-                //handle right
-                let mut right = trail.clone();
-                right.push(true);
-                r.build_map(right, map);
+        let mut lsb = vec![];
+        {
+            let mut writer = HuffWriter::with_order(tree.clone(), &mut lsb, BitOrder::LsbFirst);
+            for value in &input {
+                writer.write(value).unwrap();
             }
         }
-    }
-}
 
-pub struct HuffBuilder<V: Eq + Copy, W: PartialOrd + Add<Output = W>> {
-    nodes: Vec<(V, W)>,
-}
+        let mut msb = vec![];
+        {
+            let mut writer = HuffWriter::with_order(tree.clone(), &mut msb, BitOrder::MsbFirst);
+            for value in &input {
+                writer.write(value).unwrap();
+            }
+        }
 
-impl<V: Eq + Copy, W: PartialOrd + Add<Output = W>> HuffBuilder<V, W> {
-    pub fn new() -> Self {
-        HuffBuilder { nodes: vec![] }
-    }
+        assert_eq!(vec![0b_00101100], msb);
+        assert_eq!(vec![0b_00110100], lsb);
 
-    pub fn add(mut self, sym: V, weight: W) -> Self {
-        self.nodes.push((sym, weight));
-        self
+        let order = BitOrder::LsbFirst;
+        let mut reader = HuffReader::with_order(tree.clone(), Cursor::new(lsb.clone()), order);
+        let output = reader.by_ref().take(4).collect::<std::io::Result<Vec<_>>>().unwrap();
+
+        assert_eq!(input.to_vec(), output);
+
+        let mut reader = HuffReader::with_order(tree, Cursor::new(lsb), BitOrder::MsbFirst);
+        let output = reader.by_ref().take(4).collect::<std::io::Result<Vec<_>>>().unwrap();
+
+        assert_ne!(input.to_vec(), output);
     }
 
-    pub fn build(mut self) -> Option<HuffTree<V>> {
-        use std::cmp::Ordering;
+    #[test]
+    fn tree_symbols() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
 
-        self.nodes.sort_by(|a, b| if b.1 > a.1 {
-            Ordering::Greater
-        } else if b.1 < a.1 {
-            Ordering::Less
-        } else {
-            Ordering::Equal
-        });
+        assert_eq!(vec!['d', 'b', 'a'], tree.symbols());
 
-        let mut nodes: Vec<(HuffTree<V>, W)> = self.nodes
-            .into_iter()
-            .map(|(v, w)| (HuffTree::new_leaf(v), w))
-            .collect();
+        assert!(tree.contains(&'a'));
+        assert!(tree.contains(&'d'));
+        assert!(!tree.contains(&'c'));
+    }
 
+    #[test]
+    fn encoded_bit_len() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
 
-        while nodes.len() > 1 {
-            let (right_value, right_weight) = nodes.pop().unwrap();
-            let (left_value, left_weight) = nodes.pop().unwrap();
+        let data = ['d', 'b', 'a', 'd'];
 
-            let new_weight = left_weight + right_weight;
+        assert_eq!(6, tree.encoded_bit_len(&data).unwrap());
 
-            let node = HuffTree::new_node(left_value, right_value);
+        let writer = HuffWriter::new(tree.clone(), vec![]);
 
-            let pos = {
-                match nodes.binary_search_by(|a| if new_weight > a.1 {
-                    Ordering::Greater
-                } else if new_weight < a.1 {
-                    Ordering::Less
-                } else {
-                    Ordering::Equal
-                }) {
-                    Ok(i) => i,
-                    Err(i) => i,
-                }
-            };
-            nodes.insert(pos, (node, new_weight));
-        }
+        assert_eq!(6, writer.encoded_bit_len(&data).unwrap());
+        assert_eq!(0, writer.encoded_bit_len(&[]).unwrap());
 
-        match nodes.pop() {
-            Some((val, _)) => Some(val),
-            None => None,
-        }
+        let err = tree.encoded_bit_len(&['d', 'x']).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
     }
-}
 
-impl<V: Eq + Copy + Hash, W: PartialOrd + Add<Output = W>> HuffBuilder<V, W> {
-    pub fn add_table<I>(mut self, table: I) -> Self
-    where
-        I: IntoIterator<Item = (V, W)>,
-    {
-        for (val, weight) in table {
-            self.nodes.push((val, weight));
-        }
+    #[cfg(feature = "serde")]
+    #[test]
+    fn tree_json_roundtrip() {
+        extern crate serde_json;
 
-        self
-    }
-}
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
 
-pub struct HuffWriter<V: Eq + Copy + Hash, W: Write> {
-    encoding: HashMap<V, Vec<bool>>,
-    writer: BitWriter<W, NoPadding>,
-}
+        let json = serde_json::to_string(&tree).unwrap();
 
-impl<V: Eq + Copy + Hash, W: Write> HuffWriter<V, W> {
-    pub fn new(tree: HuffTree<V>, writer: W) -> Self {
-        HuffWriter {
-            encoding: tree.encoding(),
-            writer: BitWriter::new(writer),
-        }
+        assert_eq!(tree, serde_json::from_str(&json).unwrap());
     }
 
-    pub fn write(&mut self, value: &V) -> std::io::Result<()> {
-        let bits: &Vec<bool> = match self.encoding.get(value) {
-            Some(bits) => bits,
-            None => {
-                return Err(Error::from(ErrorKind::InvalidInput));
-            }
-        };
+    #[test]
+    fn build_checked_overflow() {
+        let result = HuffBuilder::<char, u32>::new()
+            .add('a', u32::MAX - 10)
+            .add('b', u32::MAX - 10)
+            .add('c', 1)
+            .build_checked();
 
-        for bit in bits {
-            self.writer.write_bit(*bit)?;
-        }
+        assert_eq!(Err(BuildError::WeightOverflow), result);
 
-        Ok(())
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build_checked();
+
+        assert_eq!(
+            HuffBuilder::<char, u32>::new().add('a', 1).add('b', 2).add('d', 10).try_build(),
+            tree
+        );
     }
-}
 
-pub struct HuffReader<V: Eq + Copy, R: Read> {
-    tree: Box<HuffTree<V>>,
-    reader: BitReader<R, NoPadding>,
-}
+    #[test]
+    fn reader_position() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
 
-impl<V: Eq + Copy, R: Read> HuffReader<V, R> {
-    pub fn new(tree: HuffTree<V>, reader: R) -> Self {
-        HuffReader {
-            tree: Box::new(tree),
-            reader: BitReader::new(reader),
+        let input = vec![0b_01011000, 0xAB, 0xCD];
+
+        let mut reader = HuffReader::new(tree, Cursor::new(input));
+        for _ in 0..4 {
+            reader.read().unwrap();
         }
-    }
 
-    pub fn read(&mut self) -> std::io::Result<V> {
+        assert_eq!(6, reader.bits_read());
 
-        let mut cursor = &self.tree;
+        let mut rest = vec![];
+        reader.into_inner().read_to_end(&mut rest).unwrap();
 
-        loop {
-            match **cursor {
-                HuffTree::Leaf(ref value) => return Ok(*value),
-                HuffTree::Node(ref l, ref r) => {
-                    let bit = self.reader.read_bit()?;
-                    match bit {
-                        Some(b) => {
-                            cursor = if b { r } else { l };
-                        }
-                        None => return Err(Error::from(ErrorKind::UnexpectedEof)),
-                    }
-                }
-            };
-        }
+        assert_eq!(vec![0xAB, 0xCD], rest);
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Cursor;
 
     #[test]
-    fn build_simple_tree() {
+    fn write_all_values() {
         let tree = HuffBuilder::<char, u32>::new()
             .add('a', 1)
             .add('b', 2)
@@ -206,49 +4804,81 @@ mod tests {
             .build()
             .unwrap();
 
-        let expected = HuffTree::new_node(
-            HuffTree::new_leaf('d'),
-            HuffTree::new_node(HuffTree::new_leaf('b'), HuffTree::new_leaf('a')),
-        );
+        let mut writer = HuffWriter::new(tree.clone(), vec![]);
+        writer.write_all(&['d', 'b', 'a', 'd']).unwrap();
 
-        assert_eq!(expected, tree);
+        assert_eq!(vec![0b_01011000], writer.finish().unwrap());
+
+        let mut writer = HuffWriter::new(tree, vec![]);
+        let err = writer.write_all(&['d', 'b', 'x', 'd']).unwrap_err();
+
+        let huff_err = err.get_ref().and_then(|e| e.downcast_ref::<HuffError>());
+
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        assert_eq!(Some(&HuffError::UnknownSymbolAt(2)), huff_err);
+        assert!(err.to_string().contains("index 2"));
     }
 
     #[test]
-    fn build_flat_tree() {
+    fn peek_then_read() {
         let tree = HuffBuilder::<char, u32>::new()
             .add('a', 1)
-            .add('b', 1)
-            .add('c', 1)
-            .add('d', 1)
+            .add('b', 2)
+            .add('d', 10)
             .build()
             .unwrap();
 
-        let expected = HuffTree::new_node(
-            HuffTree::new_node(HuffTree::new_leaf('a'), HuffTree::new_leaf('b')),
-            HuffTree::new_node(HuffTree::new_leaf('c'), HuffTree::new_leaf('d')),
-        );
+        let mut reader = HuffReader::new(tree, Cursor::new(vec![0b_01011000]));
 
-        assert_eq!(expected, tree);
+        for expected in &['d', 'b', 'a', 'd'] {
+            assert_eq!(Some(*expected), reader.peek().unwrap());
+            assert_eq!(Some(*expected), reader.peek().unwrap());
+            assert_eq!(*expected, reader.read().unwrap());
+        }
+
+        assert_eq!(6, reader.bits_read());
     }
 
     #[test]
-    fn build_tree_from_table() {
-        let mut table = HashMap::new();
-        {
-            table.insert('a', 2);
-            table.insert('b', 1);
-        }
+    fn reader_from_stream() {
+        let tree = HuffBuilder::<u8, u32>::new()
+            .add(b'a', 1)
+            .add(b'b', 2)
+            .add(b'd', 10)
+            .build()
+            .unwrap();
 
-        let tree = HuffBuilder::new().add_table(table).build().unwrap();
+        let mut output = vec![];
+        tree.serialize(&mut output).unwrap();
 
-        let expected = HuffTree::new_node(HuffTree::new_leaf('a'), HuffTree::new_leaf('b'));
+        let mut writer = HuffWriter::new(tree, output);
+        writer.write_all(b"dbad").unwrap();
+        let output = writer.finish().unwrap();
 
-        assert_eq!(expected, tree);
+        let mut reader = HuffReader::<u8, _>::from_stream(Cursor::new(output)).unwrap();
+        let decoded = reader.by_ref().take(4).collect::<std::io::Result<Vec<_>>>().unwrap();
+
+        assert_eq!(b"dbad".to_vec(), decoded);
     }
 
     #[test]
-    fn encoding_map() {
+    fn tree_imbalance() {
+        assert_eq!(1.0, HuffTree::fixed(0..8u8).unwrap().imbalance());
+        assert_eq!(1.5, HuffTree::fixed(0..5u8).unwrap().imbalance());
+        assert_eq!(1.0, HuffTree::new_leaf('a').imbalance());
+
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('c', 4)
+            .add('d', 8)
+            .build()
+            .unwrap();
+        assert_eq!(3.0, tree.imbalance());
+    }
+
+    #[test]
+    fn depth_histogram() {
         let tree = HuffBuilder::<char, u32>::new()
             .add('a', 1)
             .add('b', 1)
@@ -257,77 +4887,122 @@ mod tests {
             .build()
             .unwrap();
 
-        let mut expected = HashMap::new();
-        expected.insert('a', vec![false, false]);
-        expected.insert('b', vec![false, true]);
-        expected.insert('c', vec![true, false]);
-        expected.insert('d', vec![true, true]);
+        assert_eq!(vec![0, 0, 4], tree.depth_histogram());
 
-        assert_eq!(expected, tree.encoding());
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('c', 4)
+            .add('d', 10)
+            .build()
+            .unwrap();
+
+        assert_eq!(vec![0, 1, 1, 2], tree.depth_histogram());
+        assert_eq!(vec![0, 1], HuffTree::new_leaf('a').depth_histogram());
     }
 
     #[test]
-    fn encode() {
+    fn count_leaves_and_nodes() {
         let tree = HuffBuilder::<char, u32>::new()
             .add('a', 1)
-            .add('b', 1)
-            .add('c', 1)
-            .add('d', 1)
+            .add('b', 2)
+            .add('c', 4)
+            .add('d', 10)
             .build()
             .unwrap();
 
-        let mut output: Vec<u8> = vec![];
-        {
-            let mut writer = HuffWriter::new(tree, &mut output);
+        assert_eq!(4, tree.count_leaves());
+        assert_eq!(3, tree.count_nodes());
+        assert_eq!(1, HuffTree::new_leaf('a').count_leaves());
+        assert_eq!(0, HuffTree::new_leaf('a').count_nodes());
+    }
 
-            for value in vec!['a', 'b', 'c', 'd', 'a'] {
-                writer.write(&value).unwrap();
-            }
-        }
+    #[test]
+    fn validate_duplicate_symbols() {
+        let tree = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
 
-        let expected = vec![0b_00011011, 0b_00000000];
+        assert_eq!(Ok(()), tree.validate());
 
-        assert_eq!(expected, output);
+        let tree = HuffTree::new_node(
+            HuffTree::new_leaf('a'),
+            HuffTree::new_node(HuffTree::new_leaf('b'), HuffTree::new_leaf('a')),
+        );
+
+        assert_eq!(Err(TreeError::DuplicateSymbol('a')), tree.validate());
     }
 
     #[test]
-    fn encode_value_error() {
-        let tree = HuffBuilder::<char, u32>::new().add('a', 1).build().unwrap();
+    fn same_codes_ignores_arrangement() {
+        let a = HuffTree::new_leaf('a');
+        let b = HuffTree::new_leaf('b');
+        let c = HuffTree::new_leaf('c');
 
-        let mut output: Vec<u8> = vec![];
-        {
-            let mut writer = HuffWriter::new(tree, &mut output);
+        let tree = HuffTree::new_node(a.clone(), HuffTree::new_node(b.clone(), c.clone()));
+        let mirrored = HuffTree::new_node(HuffTree::new_node(c.clone(), b.clone()), a.clone());
+        let other = HuffTree::new_node(b.clone(), HuffTree::new_node(a, c));
 
-            for value in vec!['b'] {
-                match writer.write(&value) {
-                    Ok(_) => panic!(),
-                    Err(_) => (),
-                }
-            }
-        }
+        assert!(tree != mirrored);
+        assert!(tree.same_codes(&mirrored));
+        assert!(!tree.same_codes(&other));
+        assert!(!tree.same_codes(&b));
     }
 
+    #[test]
+    fn join_disjoint_trees() {
+        let left = HuffTree::new_node(HuffTree::new_leaf('a'), HuffTree::new_leaf('b'));
+        let right = HuffTree::new_node(HuffTree::new_leaf('c'), HuffTree::new_leaf('d'));
+
+        let joined = HuffTree::try_join(left.clone(), right.clone()).unwrap();
+        assert_eq!(HuffTree::join(left.clone(), right), joined);
+        assert_eq!(Some(vec![('a', 2), ('b', 2), ('c', 2), ('d', 2)]), joined.code_lengths());
+
+        let right = HuffTree::new_node(HuffTree::new_leaf('c'), HuffTree::new_leaf('b'));
+        assert_eq!(Err(TreeError::DuplicateSymbol('b')), HuffTree::try_join(left, right));
+    }
 
     #[test]
-    fn decode() {
+    fn build_from_probabilities() {
+        let probs = [('c', 0.3), ('a', 0.1), ('d', 0.4), ('b', 0.2)];
+        let tree = HuffBuilder::from_probabilities(&probs).build().unwrap();
+
+        // 0.1 + 0.2 rounds to just above 0.3, so the merged node sorts ahead of 'c'
+        let expected = HuffTree::new_node(
+            HuffTree::new_node(
+                HuffTree::new_node(HuffTree::new_leaf('b'), HuffTree::new_leaf('a')),
+                HuffTree::new_leaf('c'),
+            ),
+            HuffTree::new_leaf('d'),
+        );
+
+        assert_eq!(expected, tree);
 
+        let probs = [('d', 0.4), ('b', 0.2), ('c', 0.3), ('a', 0.1)];
+        let reordered = HuffBuilder::from_probabilities(&probs).build().unwrap();
+
+        assert_eq!(tree, reordered);
+    }
+
+    #[test]
+    fn write_iter_chars() {
         let tree = HuffBuilder::<char, u32>::new()
             .add('a', 1)
-            .add('b', 1)
-            .add('c', 1)
-            .add('d', 1)
+            .add('b', 2)
+            .add('d', 10)
             .build()
             .unwrap();
 
-        let input = vec![0b_00011011, 0b_00000000];
+        let mut writer = HuffWriter::new(tree.clone(), vec![]);
 
-        let mut reader = HuffReader::new(tree, Cursor::new(input));
-        let mut output = vec![];
+        assert_eq!(4, writer.write_iter("dbad".chars()).unwrap());
+        assert_eq!(vec![0b_01011000], writer.finish().unwrap());
 
-        for _ in 0..5 {
-            output.push(reader.read().unwrap());
-        }
+        let mut writer = HuffWriter::new(tree, vec![]);
 
-        assert_eq!(vec!['a', 'b', 'c', 'd', 'a'], output);
+        assert!(writer.write_iter("dbxd".chars()).is_err());
     }
 }
@@ -0,0 +1,35 @@
+//! The maps used for code tables, which depend on whether `std` is
+//! available.
+
+#[cfg(feature = "std")]
+use core::hash::Hash;
+
+/// The map type code tables are returned in: a `HashMap` with the `std`
+/// feature and a `BTreeMap` without it.
+#[cfg(feature = "std")]
+pub type Map<K, V> = std::collections::HashMap<K, V>;
+#[cfg(not(feature = "std"))]
+pub type Map<K, V> = alloc::collections::BTreeMap<K, V>;
+
+#[cfg(feature = "std")]
+pub type Set<K> = std::collections::HashSet<K>;
+#[cfg(not(feature = "std"))]
+pub type Set<K> = alloc::collections::BTreeSet<K>;
+
+/// Symbols that can be used as keys of a `Map`: `Eq + Hash` with the `std`
+/// feature and `Ord` without it.
+///
+/// Implemented for every type meeting those bounds.
+#[cfg(feature = "std")]
+pub trait MapKey: Eq + Hash {}
+#[cfg(feature = "std")]
+impl<T: Eq + Hash> MapKey for T {}
+
+/// Symbols that can be used as keys of a `Map`: `Eq + Hash` with the `std`
+/// feature and `Ord` without it.
+///
+/// Implemented for every type meeting those bounds.
+#[cfg(not(feature = "std"))]
+pub trait MapKey: Ord {}
+#[cfg(not(feature = "std"))]
+impl<T: Ord> MapKey for T {}
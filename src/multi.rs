@@ -0,0 +1,196 @@
+use std::fmt::Debug;
+use std::io::prelude::*;
+use std::io::{Cursor, Error, ErrorKind, Result};
+
+use map::MapKey;
+use {HuffReader, HuffTree, HuffWriter};
+
+/// Writes several independent streams of symbols, each with its own tree,
+/// into one output.
+///
+/// Each stream is held in memory until `finish`, which writes a header of
+/// the number of streams as a big endian `u32` followed by the length in
+/// bits of each stream as a big endian `u64`, and then the streams one after
+/// another, each padded to a whole byte. `MultiHuffReader` reads it back.
+pub struct MultiHuffWriter<V: Eq + Clone + MapKey, W: Write> {
+    streams: Vec<HuffWriter<V, Vec<u8>>>,
+    writer: W,
+}
+
+impl<V: Eq + Clone + MapKey, W: Write> MultiHuffWriter<V, W> {
+    /// Creates a writer with a stream for each tree, numbered in order.
+    pub fn new(trees: Vec<HuffTree<V>>, writer: W) -> Self {
+        MultiHuffWriter {
+            streams: trees.into_iter().map(|tree| HuffWriter::new(tree, vec![])).collect(),
+            writer,
+        }
+    }
+
+    /// Writes the code for `value` to stream `index`.
+    ///
+    /// Fails with `InvalidInput` if there is no such stream, or the symbol
+    /// is not in its tree.
+    pub fn write(&mut self, index: usize, value: &V) -> Result<()>
+    where
+        V: Debug,
+    {
+        match self.streams.get_mut(index) {
+            Some(stream) => stream.write(value),
+            None => Err(no_stream(index)),
+        }
+    }
+
+    /// Writes the header and every stream, handing back the inner writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.write_all(&(self.streams.len() as u32).to_be_bytes())?;
+        for stream in &self.streams {
+            self.writer.write_all(&stream.bits_written().to_be_bytes())?;
+        }
+
+        for stream in self.streams {
+            self.writer.write_all(&stream.finish()?)?;
+        }
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// The reader of one stream of a `MultiHuffReader`, over that stream's
+/// bytes.
+pub type StreamReader<V> = HuffReader<V, Cursor<Vec<u8>>>;
+
+/// Reads back the streams written by a `MultiHuffWriter`.
+///
+/// The whole input is read up front, so each stream can then be read on its
+/// own and in any order.
+pub struct MultiHuffReader<V: Eq + Clone> {
+    streams: Vec<(StreamReader<V>, u64)>,
+}
+
+impl<V: Eq + Clone> MultiHuffReader<V> {
+    /// Reads the header and every stream, decoding stream `i` with tree `i`.
+    ///
+    /// Fails with `InvalidData` if the header records a different number of
+    /// streams than there are trees, and with `UnexpectedEof` if the input
+    /// ends early.
+    pub fn new<R: Read>(trees: Vec<HuffTree<V>>, mut reader: R) -> Result<Self> {
+        let mut count = [0; 4];
+        reader.read_exact(&mut count)?;
+        let count = u32::from_be_bytes(count) as usize;
+        if count != trees.len() {
+            let msg = format!("input holds {} streams, not {}", count, trees.len());
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+
+        let mut lengths = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut len = [0; 8];
+            reader.read_exact(&mut len)?;
+            lengths.push(u64::from_be_bytes(len));
+        }
+
+        let mut streams = Vec::with_capacity(count);
+        for (tree, bit_len) in trees.into_iter().zip(lengths) {
+            // read through `take` so a corrupt length cannot ask for a huge
+            // buffer up front
+            let byte_len = bit_len.div_ceil(8);
+            let mut bytes = vec![];
+            (&mut reader).take(byte_len).read_to_end(&mut bytes)?;
+            if (bytes.len() as u64) < byte_len {
+                return Err(Error::from(ErrorKind::UnexpectedEof));
+            }
+
+            streams.push((HuffReader::new(tree, Cursor::new(bytes)), bit_len));
+        }
+
+        Ok(MultiHuffReader { streams })
+    }
+
+    /// Number of streams.
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    /// Decodes the next symbol of stream `index`, `None` once all of its
+    /// bits have been read, so the padding after it is never decoded.
+    ///
+    /// Fails with `InvalidInput` if there is no such stream.
+    pub fn read(&mut self, index: usize) -> Result<Option<V>> {
+        let (ref mut reader, bit_len) = match self.streams.get_mut(index) {
+            Some(stream) => stream,
+            None => return Err(no_stream(index)),
+        };
+
+        if reader.bits_read() >= *bit_len {
+            return Ok(None);
+        }
+
+        reader.read().map(Some)
+    }
+
+    /// Hands back the reader of each stream along with its length in bits,
+    /// past which the reader decodes padding.
+    pub fn into_streams(self) -> Vec<(StreamReader<V>, u64)> {
+        self.streams
+    }
+}
+
+fn no_stream(index: usize) -> Error {
+    Error::new(ErrorKind::InvalidInput, format!("there is no stream {}", index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use HuffBuilder;
+
+    #[test]
+    fn multi_roundtrip() {
+        let letters = HuffBuilder::<char, u32>::new()
+            .add('a', 1)
+            .add('b', 2)
+            .add('d', 10)
+            .build()
+            .unwrap();
+        let digits = HuffTree::fixed("0123456789".chars()).unwrap();
+        let trees = vec![letters, digits];
+
+        let mut writer = MultiHuffWriter::new(trees.clone(), vec![]);
+        for (letter, digit) in "abdda".chars().zip("31415".chars()) {
+            writer.write(0, &letter).unwrap();
+            writer.write(1, &digit).unwrap();
+        }
+        writer.write(1, &'9').unwrap();
+        assert_eq!(ErrorKind::InvalidInput, writer.write(2, &'a').unwrap_err().kind());
+
+        let bytes = writer.finish().unwrap();
+        // the count, two lengths, then a byte of letters and three of digits
+        assert_eq!(4 + 16 + 1 + 3, bytes.len());
+
+        let mut reader = MultiHuffReader::new(trees.clone(), Cursor::new(bytes.clone())).unwrap();
+        assert_eq!(2, reader.len());
+
+        let mut digits = String::new();
+        while let Some(digit) = reader.read(1).unwrap() {
+            digits.push(digit);
+        }
+        let mut letters = String::new();
+        while let Some(letter) = reader.read(0).unwrap() {
+            letters.push(letter);
+        }
+
+        assert_eq!("abdda", letters);
+        assert_eq!("314159", digits);
+
+        let err = MultiHuffReader::new(trees[..1].to_vec(), Cursor::new(bytes.clone()));
+        assert_eq!(ErrorKind::InvalidData, err.err().unwrap().kind());
+
+        let err = MultiHuffReader::new(trees, Cursor::new(&bytes[..bytes.len() - 1]));
+        assert_eq!(ErrorKind::UnexpectedEof, err.err().unwrap().kind());
+    }
+}
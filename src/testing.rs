@@ -0,0 +1,42 @@
+//! Helpers for testing symbol types against the codec.
+
+use std::hash::Hash;
+use std::io::Cursor;
+
+use {huff_tree_from_slice, HuffWriter};
+
+/// Builds a tree from `weights`, encodes `data` with it and decodes the
+/// result, returning the decoded symbols.
+///
+/// Exactly `data.len()` symbols are decoded, so the padding bits in the last
+/// byte never show up as extra symbols. Panics if the tree cannot be built or
+/// a symbol in `data` is missing from `weights`.
+pub fn roundtrip<V>(weights: &[(V, u32)], data: &[V]) -> Vec<V>
+where
+    V: Eq + Clone + Hash + Ord,
+{
+    let tree = huff_tree_from_slice(weights).expect("failed to build tree");
+
+    let mut writer = HuffWriter::new(tree.clone(), vec![]);
+    writer.write_all(data).expect("failed to encode data");
+    let encoded = writer.finish().expect("failed to encode data");
+
+    tree.decode_all(Cursor::new(encoded), data.len())
+        .expect("failed to decode data")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip_any_length() {
+        let weights = [('a', 5), ('b', 2), ('c', 1)];
+
+        for len in 0..20 {
+            let data: Vec<char> = "abacabcaabacbbacaaba".chars().take(len).collect();
+
+            assert_eq!(data, roundtrip(&weights, &data));
+        }
+    }
+}